@@ -0,0 +1,273 @@
+use std::any::Any;
+use std::collections::HashMap;
+
+use crate::error::{FlacError, Offset, Result};
+
+// блок APPLICATION: данные стороннего приложения, хранятся как есть
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Application {
+    // зарегистрированный идентификатор приложения, обычно 4 ASCII символа
+    pub id: [u8; 4],
+    pub data: Vec<u8>,
+}
+
+impl Application {
+    pub fn process_application_block(application_block: &[u8], offset: u64) -> Result<Self> {
+        let (id, data) =
+            application_block
+                .split_first_chunk::<4>()
+                .ok_or(FlacError::TruncatedMetadata {
+                    block_type: 2,
+                    offset: Offset::from_bytes(offset),
+                })?;
+
+        Ok(Application {
+            id: *id,
+            data: data.to_vec(),
+        })
+    }
+
+    // содержимое блока APPLICATION
+    #[must_use]
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut bytes = Vec::with_capacity(self.id.len() + self.data.len());
+        bytes.extend_from_slice(&self.id);
+        bytes.extend_from_slice(&self.data);
+        bytes
+    }
+}
+
+// содержимое блока, разобранное парсером из реестра
+#[derive(Debug)]
+pub enum ApplicationContent {
+    // чанки исходного WAV или AIFF, которые сохраняет flac --keep-foreign-metadata
+    ForeignMetadata(ForeignMetadata),
+    // результат стороннего парсера, достается через downcast_ref
+    Custom(Box<dyn Any>),
+}
+
+// разбор данных приложения, None если данные не получилось разобрать
+pub trait ApplicationParser {
+    fn parse(&self, data: &[u8]) -> Option<ApplicationContent>;
+}
+
+impl<F: Fn(&[u8]) -> Option<ApplicationContent>> ApplicationParser for F {
+    fn parse(&self, data: &[u8]) -> Option<ApplicationContent> {
+        self(data)
+    }
+}
+
+// парсеры известных приложений по идентификатору
+// неизвестные и неразобранные блоки остаются байтами в Application::data
+pub struct ApplicationRegistry {
+    parsers: HashMap<[u8; 4], Box<dyn ApplicationParser>>,
+}
+
+impl ApplicationRegistry {
+    // реестр с парсерами "riff" и "aiff"
+    #[must_use]
+    pub fn new() -> Self {
+        let mut registry = ApplicationRegistry::empty();
+        registry.register(*b"riff", |data: &[u8]| {
+            ForeignMetadata::parse(ForeignFormat::Riff, data)
+                .map(ApplicationContent::ForeignMetadata)
+        });
+        registry.register(*b"aiff", |data: &[u8]| {
+            ForeignMetadata::parse(ForeignFormat::Aiff, data)
+                .map(ApplicationContent::ForeignMetadata)
+        });
+        registry
+    }
+
+    #[must_use]
+    pub fn empty() -> Self {
+        ApplicationRegistry {
+            parsers: HashMap::new(),
+        }
+    }
+
+    // парсер для id, заменяет зарегистрированный ранее
+    pub fn register(&mut self, id: [u8; 4], parser: impl ApplicationParser + 'static) {
+        self.parsers.insert(id, Box::new(parser));
+    }
+
+    // None если приложение неизвестно или данные не разобрались
+    #[must_use]
+    pub fn parse(&self, application: &Application) -> Option<ApplicationContent> {
+        self.parsers.get(&application.id)?.parse(&application.data)
+    }
+}
+
+impl Default for ApplicationRegistry {
+    fn default() -> Self {
+        ApplicationRegistry::new()
+    }
+}
+
+// формат исходного файла: от него зависит порядок байт в размерах чанков
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ForeignFormat {
+    Riff,
+    Aiff,
+}
+
+// чанки исходного файла из одного блока APPLICATION
+// в первом блоке лежат заголовок RIFF/FORM и чанки до аудио,
+// чанк с аудио (data или SSND) обрезан: сами сэмплы хранятся во фреймах FLAC
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ForeignMetadata {
+    pub format: ForeignFormat,
+    pub chunks: Vec<ForeignChunk>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ForeignChunk {
+    pub id: [u8; 4],
+    // размер из заголовка чанка, для обрезанного чанка больше data
+    pub size: u32,
+    pub data: Vec<u8>,
+}
+
+impl ForeignMetadata {
+    // None если данные не делятся на чанки
+    #[must_use]
+    pub fn parse(format: ForeignFormat, data: &[u8]) -> Option<Self> {
+        let mut chunks = Vec::new();
+        let mut rest = data;
+
+        while !rest.is_empty() {
+            let (id, tail) = rest.split_first_chunk::<4>()?;
+            let (size, tail) = tail.split_first_chunk::<4>()?;
+            let size = match format {
+                ForeignFormat::Riff => u32::from_le_bytes(*size),
+                ForeignFormat::Aiff => u32::from_be_bytes(*size),
+            };
+
+            // у контейнера внутри только тип формы, остальное - следующие чанки
+            let length = match id {
+                b"RIFF" | b"RF64" | b"FORM" => 4,
+                _ => size as usize,
+            };
+            let length = length.min(tail.len());
+            // чанки выровнены по 2 байта
+            let padded = (length + length % 2).min(tail.len());
+
+            chunks.push(ForeignChunk {
+                id: *id,
+                size,
+                data: tail[..length].to_vec(),
+            });
+            rest = &tail[padded..];
+        }
+
+        Some(ForeignMetadata { format, chunks })
+    }
+
+    // первый чанк с таким id
+    #[must_use]
+    pub fn chunk(&self, id: &[u8; 4]) -> Option<&ForeignChunk> {
+        self.chunks.iter().find(|chunk| &chunk.id == id)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // заголовок чанка и содержимое как есть, без выравнивания
+    fn chunk(bytes: &mut Vec<u8>, format: ForeignFormat, id: [u8; 4], size: u32, data: &[u8]) {
+        bytes.extend_from_slice(&id);
+        match format {
+            ForeignFormat::Riff => bytes.extend_from_slice(&size.to_le_bytes()),
+            ForeignFormat::Aiff => bytes.extend_from_slice(&size.to_be_bytes()),
+        }
+        bytes.extend_from_slice(data);
+    }
+
+    fn foreign_chunk(id: [u8; 4], size: u32, data: &[u8]) -> ForeignChunk {
+        ForeignChunk {
+            id,
+            size,
+            data: data.to_vec(),
+        }
+    }
+
+    #[test]
+    fn riff_chunks() {
+        let format = ForeignFormat::Riff;
+        let mut data = Vec::new();
+        chunk(&mut data, format, *b"RIFF", 100_036, b"WAVE");
+        chunk(&mut data, format, *b"fmt ", 16, &[1; 16]);
+        // нечетный размер, за содержимым байт выравнивания
+        chunk(&mut data, format, *b"LIST", 3, b"abc\0");
+        // сэмплы во фреймах FLAC, от data остается только начало
+        chunk(&mut data, format, *b"data", 100_000, &[2; 6]);
+
+        let application = Application { id: *b"riff", data };
+        let Some(ApplicationContent::ForeignMetadata(metadata)) =
+            ApplicationRegistry::new().parse(&application)
+        else {
+            panic!("RIFF chunks are not parsed");
+        };
+
+        assert_eq!(metadata.format, format);
+        assert_eq!(
+            metadata.chunks,
+            [
+                foreign_chunk(*b"RIFF", 100_036, b"WAVE"),
+                foreign_chunk(*b"fmt ", 16, &[1; 16]),
+                foreign_chunk(*b"LIST", 3, b"abc"),
+                foreign_chunk(*b"data", 100_000, &[2; 6]),
+            ]
+        );
+        assert_eq!(metadata.chunk(b"LIST").unwrap().data, b"abc");
+    }
+
+    #[test]
+    fn aiff_chunks() {
+        let format = ForeignFormat::Aiff;
+        let mut data = Vec::new();
+        chunk(&mut data, format, *b"FORM", 200_046, b"AIFF");
+        chunk(&mut data, format, *b"COMM", 18, &[3; 18]);
+        chunk(&mut data, format, *b"NAME", 5, b"hello\0");
+        // у SSND впереди offset и block size, дальше сэмплы
+        chunk(&mut data, format, *b"SSND", 200_008, &[0; 8]);
+
+        let metadata = ForeignMetadata::parse(format, &data).unwrap();
+        assert_eq!(
+            metadata.chunks,
+            [
+                foreign_chunk(*b"FORM", 200_046, b"AIFF"),
+                foreign_chunk(*b"COMM", 18, &[3; 18]),
+                foreign_chunk(*b"NAME", 5, b"hello"),
+                foreign_chunk(*b"SSND", 200_008, &[0; 8]),
+            ]
+        );
+    }
+
+    #[test]
+    fn truncated_chunks() {
+        let format = ForeignFormat::Riff;
+
+        // последний нечетный чанк без байта выравнивания
+        let mut data = Vec::new();
+        chunk(&mut data, format, *b"LIST", 3, b"abc");
+        assert_eq!(
+            ForeignMetadata::parse(format, &data).unwrap().chunks,
+            [foreign_chunk(*b"LIST", 3, b"abc")]
+        );
+
+        // от data остался только заголовок
+        let mut data = Vec::new();
+        chunk(&mut data, format, *b"data", 100_000, &[]);
+        assert_eq!(
+            ForeignMetadata::parse(format, &data).unwrap().chunks,
+            [foreign_chunk(*b"data", 100_000, &[])]
+        );
+
+        // заголовок чанка оборван
+        for length in [3, 7] {
+            assert_eq!(ForeignMetadata::parse(format, &data[..length]), None);
+        }
+    }
+}
@@ -0,0 +1,199 @@
+use std::io::{self, Read, Seek, SeekFrom};
+
+// CRC-8 заголовка фрейма: полином x^8 + x^2 + x^1 + x^0 (0x07), начальное значение 0
+#[must_use]
+pub fn crc8(data: &[u8]) -> u8 {
+    let mut crc = 0u8;
+
+    for &byte in data {
+        crc ^= byte;
+        for _ in 0..8 {
+            crc = if crc & 0x80 != 0 {
+                (crc << 1) ^ 0x07
+            } else {
+                crc << 1
+            };
+        }
+    }
+
+    crc
+}
+
+// CRC-16 всего фрейма: полином x^16 + x^15 + x^2 + x^0 (0x8005), начальное значение 0
+#[must_use]
+pub fn crc16(data: &[u8]) -> u16 {
+    let mut crc = 0u16;
+
+    for &byte in data {
+        crc ^= u16::from(byte) << 8;
+        for _ in 0..8 {
+            crc = if crc & 0x8000 != 0 {
+                (crc << 1) ^ 0x8005
+            } else {
+                crc << 1
+            };
+        }
+    }
+
+    crc
+}
+
+// что делать при несовпадении контрольной суммы
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CrcMode {
+    // ошибка на первом же битом фрейме
+    Strict,
+    // только сообщить и продолжить декодирование
+    Lenient,
+}
+
+// результат проверки контрольных сумм одного фрейма
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct CrcStatus {
+    pub header_crc8: bool,
+    pub frame_crc16: bool,
+}
+
+impl CrcStatus {
+    #[must_use]
+    pub fn is_valid(self) -> bool {
+        self.header_crc8 && self.frame_crc16
+    }
+}
+
+// обертка над ридером, которая запоминает все прочитанные байты,
+// чтобы посчитать CRC ровно по тем байтам, которые разобрал декодер
+pub struct CrcReader<R> {
+    inner: R,
+    bytes: Vec<u8>,
+    // возвращенные через unread байты, читаются раньше inner, последний - первым
+    replay: Vec<u8>,
+    // смещение от начала потока, для сообщений об ошибках
+    position: u64,
+}
+
+impl<R> CrcReader<R> {
+    // position - сколько байт потока уже прочитано до inner
+    pub fn new(inner: R, position: u64) -> Self {
+        CrcReader {
+            inner,
+            bytes: Vec::new(),
+            replay: Vec::new(),
+            position,
+        }
+    }
+
+    pub fn position(&self) -> u64 {
+        self.position
+    }
+
+    // заранее выделяет память под фрейм, чтобы не расти во время декодирования
+    pub fn reserve(&mut self, bytes: usize) {
+        self.bytes.reserve(bytes);
+    }
+
+    // переход на position байт от начала потока
+    pub fn seek_to(&mut self, position: u64) -> io::Result<()>
+    where
+        R: Seek,
+    {
+        self.inner.seek(SeekFrom::Start(position))?;
+        self.position = position;
+        self.bytes.clear();
+        self.replay.clear();
+        Ok(())
+    }
+
+    // длина всего потока в байтах, позиция чтения не меняется
+    pub fn stream_len(&mut self) -> io::Result<u64>
+    where
+        R: Seek,
+    {
+        // inner может быть впереди position на длину replay
+        let current = self.inner.stream_position()?;
+        let length = self.inner.seek(SeekFrom::End(0))?;
+        self.inner.seek(SeekFrom::Start(current))?;
+        Ok(length)
+    }
+
+    // байты прочитанные с последнего clear()
+    pub fn consumed(&self) -> &[u8] {
+        &self.bytes
+    }
+
+    pub fn clear(&mut self) {
+        self.bytes.clear();
+    }
+
+    // возврат уже прочитанных байтов в поток, следующие чтения отдадут их снова
+    // позволяет искать фрейм заново без Seek
+    pub fn unread(&mut self, bytes: &[u8]) {
+        self.replay.extend(bytes.iter().rev());
+        self.position -= bytes.len() as u64;
+    }
+}
+
+impl<R: Read> Read for CrcReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let count = if self.replay.is_empty() {
+            self.inner.read(buf)?
+        } else {
+            let count = self.replay.len().min(buf.len());
+            for byte in &mut buf[..count] {
+                *byte = self.replay.pop().unwrap_or_default();
+            }
+            count
+        };
+        self.bytes.extend_from_slice(&buf[..count]);
+        self.position += count as u64;
+        Ok(count)
+    }
+}
+
+// перемещаться нельзя, Seek нужен только чтобы BitReader мог узнать свою позицию
+impl<R> Seek for CrcReader<R> {
+    fn seek(&mut self, pos: SeekFrom) -> io::Result<u64> {
+        match pos {
+            SeekFrom::Current(0) => Ok(self.position),
+            _ => Err(io::Error::new(
+                io::ErrorKind::Unsupported,
+                "CrcReader only reports its position",
+            )),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn check_values() {
+        // стандартные контрольные значения: CRC-8/SMBUS и CRC-16/UMTS
+        assert_eq!(crc8(b"123456789"), 0xF4);
+        assert_eq!(crc16(b"123456789"), 0xFEE8);
+        assert_eq!(crc8(&[]), 0);
+        assert_eq!(crc16(&[]), 0);
+    }
+
+    #[test]
+    fn frame_header_crc8() {
+        // заголовок первого фрейма 16 бит стерео 44100 Гц, блок 4096, последний байт - CRC-8
+        let header = [0xFF, 0xF8, 0xC9, 0x18, 0x00, 0xC2];
+        assert_eq!(crc8(&header[..5]), header[5]);
+    }
+
+    #[test]
+    fn appended_crc_gives_zero() {
+        // сумма, дописанная в конец данных, обнуляет CRC: так устроены заголовок и футер фрейма
+        let data = b"fLaC frame bytes";
+
+        let mut with_crc8 = data.to_vec();
+        with_crc8.push(crc8(data));
+        assert_eq!(crc8(&with_crc8), 0);
+
+        let mut with_crc16 = data.to_vec();
+        with_crc16.extend_from_slice(&crc16(data).to_be_bytes());
+        assert_eq!(crc16(&with_crc16), 0);
+    }
+}
@@ -0,0 +1,207 @@
+use std::time::Duration;
+
+use crate::error::{FlacError, Result};
+use crate::metedata_blocks::BlockReader;
+use crate::stream_info::StreamInfo;
+
+// длины полей блока CUESHEET
+const MEDIA_CATALOG_LENGTH: u32 = 128;
+const ISRC_LENGTH: u32 = 12;
+// байт с флагом CD и 258 зарезервированных байт
+const CUE_SHEET_RESERVED_LENGTH: u32 = 259;
+// байт с флагами трека и 13 зарезервированных байт
+const TRACK_RESERVED_LENGTH: u32 = 14;
+const INDEX_RESERVED_LENGTH: u32 = 3;
+
+// номер последнего трека (lead-out) для CD и для остальных носителей
+const CD_LEAD_OUT: u8 = 170;
+const LEAD_OUT: u8 = 255;
+
+// разметка диска на треки, как в .cue файле
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct CueSheet {
+    // каталожный номер носителя, пустой если не задан
+    pub media_catalog_number: String,
+    // количество сэмплов lead-in, имеет смысл только для CD
+    pub lead_in_samples: u64,
+    pub is_cd: bool,
+    // последний трек - lead-out, у него нет индексов
+    pub tracks: Vec<CueTrack>,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct CueTrack {
+    // первый сэмпл трека от начала аудио
+    pub offset: u64,
+    pub number: u8,
+    // пустой если не задан
+    pub isrc: String,
+    pub is_audio: bool,
+    pub pre_emphasis: bool,
+    pub indices: Vec<CueIndex>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct CueIndex {
+    // смещение в сэмплах от начала трека
+    pub offset: u64,
+    pub number: u8,
+}
+
+impl CueSheet {
+    pub fn process_cue_sheet_block(cue_sheet_block: &[u8], offset: u64) -> Result<Self> {
+        let mut reader = BlockReader::new(cue_sheet_block, 5, offset);
+
+        let media_catalog_number = read_ascii(&mut reader, MEDIA_CATALOG_LENGTH)?;
+        let lead_in_samples = reader.read_u64()?;
+        // старший бит - флаг CD, остальное зарезервировано
+        let flags = reader.read_bytes(CUE_SHEET_RESERVED_LENGTH)?[0];
+        let track_count = reader.read_u8()?;
+
+        let tracks = (0..track_count)
+            .map(|_| read_track(&mut reader))
+            .collect::<Result<_>>()?;
+
+        Ok(CueSheet {
+            media_catalog_number,
+            lead_in_samples,
+            is_cd: flags & 0x80 != 0,
+            tracks,
+        })
+    }
+
+    // содержимое блока CUESHEET
+    #[must_use]
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut bytes = Vec::new();
+        write_ascii(&mut bytes, &self.media_catalog_number, MEDIA_CATALOG_LENGTH);
+        bytes.extend_from_slice(&self.lead_in_samples.to_be_bytes());
+        bytes.push(if self.is_cd { 0x80 } else { 0 });
+        bytes.resize(bytes.len() + CUE_SHEET_RESERVED_LENGTH as usize - 1, 0);
+        #[allow(clippy::cast_possible_truncation)]
+        bytes.push(self.tracks.len() as u8);
+
+        for track in &self.tracks {
+            bytes.extend_from_slice(&track.offset.to_be_bytes());
+            bytes.push(track.number);
+            write_ascii(&mut bytes, &track.isrc, ISRC_LENGTH);
+            bytes.push(
+                if track.is_audio { 0 } else { 0x80 } | if track.pre_emphasis { 0x40 } else { 0 },
+            );
+            bytes.resize(bytes.len() + TRACK_RESERVED_LENGTH as usize - 1, 0);
+            #[allow(clippy::cast_possible_truncation)]
+            bytes.push(track.indices.len() as u8);
+
+            for index in &track.indices {
+                bytes.extend_from_slice(&index.offset.to_be_bytes());
+                bytes.push(index.number);
+                bytes.resize(bytes.len() + INDEX_RESERVED_LENGTH as usize, 0);
+            }
+        }
+
+        bytes
+    }
+
+    // lead-out всегда последний трек, номер зависит от носителя:
+    // вне CD трек 170 - обычный трек, а не lead-out
+    #[must_use]
+    pub fn lead_out(&self) -> Option<&CueTrack> {
+        let number = if self.is_cd { CD_LEAD_OUT } else { LEAD_OUT };
+        self.tracks.last().filter(|track| track.number == number)
+    }
+
+    // аудио треки без lead-out и треков с данными
+    pub fn audio_tracks(&self) -> impl Iterator<Item = &CueTrack> {
+        let count = self.tracks.len() - usize::from(self.lead_out().is_some());
+        self.tracks[..count].iter().filter(|track| track.is_audio)
+    }
+}
+
+impl CueTrack {
+    // начало трека от начала аудио
+    #[must_use]
+    pub fn start_time(&self, stream_info: &StreamInfo) -> Duration {
+        sample_time(self.offset, stream_info.sample_rate)
+    }
+
+    // номер сэмпла точки индекса от начала аудио
+    #[must_use]
+    pub fn index_sample(&self, index: &CueIndex) -> u64 {
+        self.offset + index.offset
+    }
+
+    // время точки индекса от начала аудио
+    #[must_use]
+    pub fn index_time(&self, index: &CueIndex, stream_info: &StreamInfo) -> Duration {
+        sample_time(self.index_sample(index), stream_info.sample_rate)
+    }
+}
+
+fn read_track(reader: &mut BlockReader) -> Result<CueTrack> {
+    let track_offset = reader.read_u64()?;
+    let number = reader.read_u8()?;
+    let isrc = read_ascii(reader, ISRC_LENGTH)?;
+
+    // старший бит 0 - аудио трек, следующий - pre-emphasis
+    let flags = reader.read_bytes(TRACK_RESERVED_LENGTH)?[0];
+
+    let index_count = reader.read_u8()?;
+    let indices = (0..index_count)
+        .map(|_| {
+            let index_offset = reader.read_u64()?;
+            let index_number = reader.read_u8()?;
+            reader.read_bytes(INDEX_RESERVED_LENGTH)?;
+            Ok(CueIndex {
+                offset: index_offset,
+                number: index_number,
+            })
+        })
+        .collect::<Result<_>>()?;
+
+    Ok(CueTrack {
+        offset: track_offset,
+        number,
+        isrc,
+        is_audio: flags & 0x80 == 0,
+        pre_emphasis: flags & 0x40 != 0,
+        indices,
+    })
+}
+
+// перевод номера сэмпла во время без потери точности на длинных потоках
+fn sample_time(sample: u64, sample_rate: u32) -> Duration {
+    if sample_rate == 0 {
+        return Duration::ZERO;
+    }
+    let sample_rate = u64::from(sample_rate);
+    let nanos = (sample % sample_rate) * 1_000_000_000 / sample_rate;
+    #[allow(clippy::cast_possible_truncation)]
+    Duration::new(sample / sample_rate, nanos as u32)
+}
+
+// ASCII строка фиксированной длины, дополненная нулями
+fn read_ascii(reader: &mut BlockReader, length: u32) -> Result<String> {
+    let offset = reader.offset();
+    let bytes = reader.read_bytes(length)?;
+    let end = bytes
+        .iter()
+        .position(|&byte| byte == 0)
+        .unwrap_or(bytes.len());
+
+    if !bytes[..end].is_ascii() {
+        return Err(FlacError::InvalidData {
+            reason: "Cue sheet string is not ASCII",
+            offset,
+        });
+    }
+
+    Ok(String::from_utf8_lossy(&bytes[..end]).into_owned())
+}
+
+// строка обрезается или дополняется нулями до length байт
+fn write_ascii(bytes: &mut Vec<u8>, string: &str, length: u32) {
+    let length = length as usize;
+    let string = &string.as_bytes()[..string.len().min(length)];
+    bytes.extend_from_slice(string);
+    bytes.resize(bytes.len() + length - string.len(), 0);
+}
@@ -0,0 +1,1063 @@
+use std::io::{self, BufReader, Read, Seek};
+use std::mem;
+
+use bitstream_io::{BigEndian, BitRead, BitReader};
+
+use crate::crc::{CrcMode, CrcReader, CrcStatus};
+use crate::error::{self, FlacError, Offset, Result};
+use crate::frame::{
+    self, BlockingStrategy, ChannelAssignment, Frame, FrameHeader, FrameInfo, FramePosition,
+    FrameReader, Gap,
+};
+use crate::md5_check::{self, Md5Status, StreamHasher};
+use crate::metedata_blocks::{self, Metadata};
+use crate::sample::{self, Sample};
+use crate::stream_info::StreamInfo;
+use crate::subframe::{Subframe, SubframeHeader, SubframeType};
+
+// интервал бисекции, который дочитывается линейно, если max_frame_size неизвестен
+const DEFAULT_LINEAR_SCAN: u64 = 64 * 1024;
+
+// что делать, если фрейм не разбирается: битые данные или потерянный синхрокод
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Recovery {
+    // ошибка возвращается вызывающему
+    Fail,
+    // поиск следующего настоящего фрейма, разрыв отдается в FrameInfo::gap
+    Skip,
+    // как Skip, но на месте потерянных сэмплов отдаются фреймы тишины
+    // не длиннее max_block_size, в том числе при потере в конце потока
+    FillSilence,
+}
+
+// декодер FLAC потока: при создании читает метаданные,
+// дальше по одному отдает аудио фреймы
+// читает последовательно, поэтому подходит любой Read: файл, Cursor, pipe, сокет
+pub struct Decoder<R> {
+    reader: FrameReader<BufReader<R>>,
+    stream_info: StreamInfo,
+    metadata: Metadata,
+    crc_mode: CrcMode,
+    recovery: Recovery,
+    // все разрывы в потоке, найденные при декодировании
+    gaps: Vec<Gap>,
+    // MD5 декодированного аудио для сравнения с STREAMINFO
+    hasher: StreamHasher,
+    // количество уже отданных сэмплов на канал, тишина на месте потерь тоже считается
+    decoded_samples: u64,
+    // сэмплы, потерянные в разрывах и не замененные тишиной
+    lost_samples: u64,
+    // первый сэмпл следующего фрейма по нумерации потока,
+    // None пока не прочитан первый фрейм: поток может быть вырезан из середины
+    next_sample: Option<u64>,
+    // буферы последнего декодированного фрейма, переиспользуются между фреймами
+    channels: Vec<Vec<i64>>,
+    subframe_headers: Vec<SubframeHeader>,
+    // сколько сэмплов отбросить в начале следующего фрейма после seek
+    skip_samples: u64,
+    // после seek аудио декодируется не целиком и MD5 не проверить
+    seeked: bool,
+    // с Recovery::FillSilence: номер следующего сэмпла тишины и сколько ее еще отдать
+    silence_start: u64,
+    silence_samples: u64,
+    // фрейм за разрывом ждет в своих буферах, пока не отдана вся тишина
+    pending_frame: Option<FrameInfo>,
+    pending_channels: Vec<Vec<i64>>,
+    pending_subframe_headers: Vec<SubframeHeader>,
+}
+
+impl<R: Read> Decoder<R> {
+    // читает сигнатуру и все блоки метаданных, ридер остается на первом фрейме
+    pub fn new(reader: R) -> Result<Self> {
+        let mut reader = BufReader::new(reader);
+
+        let metadata = metedata_blocks::read_metadata(&mut reader)?;
+        let stream_info = metadata.stream_info.clone();
+
+        // все буферы выделяются сразу по максимальным размерам из STREAMINFO,
+        // дальше декодирование обходится без выделения памяти
+        let max_block_size = usize::from(stream_info.max_block_size);
+        let channel_count = usize::from(stream_info.channels);
+
+        let mut hasher = StreamHasher::new(stream_info.bps);
+        hasher.reserve(max_block_size * channel_count);
+
+        let mut reader = CrcReader::new(reader, metadata.audio_offset);
+        // 0 значит что размер фрейма неизвестен
+        reader.reserve(stream_info.max_frame_size as usize);
+
+        let channels = (0..channel_count)
+            .map(|_| Vec::with_capacity(max_block_size))
+            .collect();
+
+        Ok(Decoder {
+            reader: BitReader::endian(reader, BigEndian),
+            stream_info,
+            metadata,
+            crc_mode: CrcMode::Lenient,
+            recovery: Recovery::Skip,
+            gaps: Vec::new(),
+            hasher,
+            decoded_samples: 0,
+            lost_samples: 0,
+            next_sample: None,
+            channels,
+            // каналов во фрейме не больше 8
+            subframe_headers: Vec::with_capacity(8),
+            skip_samples: 0,
+            seeked: false,
+            silence_start: 0,
+            silence_samples: 0,
+            pending_frame: None,
+            pending_channels: Vec::new(),
+            pending_subframe_headers: Vec::new(),
+        })
+    }
+
+    #[must_use]
+    pub fn stream_info(&self) -> &StreamInfo {
+        &self.stream_info
+    }
+
+    #[must_use]
+    pub fn metadata(&self) -> &Metadata {
+        &self.metadata
+    }
+
+    // в строгом режиме битый фрейм возвращается как ошибка
+    pub fn set_crc_mode(&mut self, crc_mode: CrcMode) {
+        self.crc_mode = crc_mode;
+    }
+
+    // поведение на битых фреймах, по умолчанию Skip
+    pub fn set_recovery(&mut self, recovery: Recovery) {
+        self.recovery = recovery;
+    }
+
+    // разрывы, найденные с начала декодирования, включая потерю в конце потока
+    #[must_use]
+    pub fn gaps(&self) -> &[Gap] {
+        &self.gaps
+    }
+
+    // количество уже отданных сэмплов на канал
+    // после seek отсчет начинается с запрошенного сэмпла
+    #[must_use]
+    pub fn decoded_samples(&self) -> u64 {
+        self.decoded_samples
+    }
+
+    // сэмплы на канал, потерянные в разрывах с Recovery::Skip
+    // вместе с decoded_samples дают позицию в потоке
+    #[must_use]
+    pub fn lost_samples(&self) -> u64 {
+        self.lost_samples
+    }
+
+    // итератор по оставшимся фреймам
+    pub fn frames(&mut self) -> Frames<'_, R> {
+        Frames {
+            decoder: self,
+            failed: false,
+        }
+    }
+
+    // декодирование следующего фрейма во внутренние буферы без выделения памяти,
+    // сэмплы доступны через channel() до следующего вызова
+    // None в конце потока
+    pub fn decode_frame(&mut self) -> Result<Option<FrameInfo>> {
+        // сначала тишина на месте потерянных фреймов, потом фрейм за разрывом
+        if self.silence_samples > 0 {
+            return Ok(Some(self.next_silence(None)));
+        }
+        if let Some(info) = self.pending_frame.take() {
+            mem::swap(&mut self.channels, &mut self.pending_channels);
+            mem::swap(
+                &mut self.subframe_headers,
+                &mut self.pending_subframe_headers,
+            );
+            self.decoded_samples += self.samples_per_channel() as u64;
+            return Ok(Some(info));
+        }
+
+        // total_samples = 0 значит что длина потока неизвестна,
+        // тогда читаем до конца файла
+        let total_samples = self.stream_info.total_samples;
+        if total_samples != 0 && self.position() >= total_samples {
+            return Ok(None);
+        }
+
+        // смещение, с которого начались битые данные
+        let mut corrupt_offset = None;
+
+        let mut info = loop {
+            match frame::read_frame_into(
+                &mut self.reader,
+                &self.stream_info,
+                &mut self.channels,
+                &mut self.subframe_headers,
+            ) {
+                Ok(info) => break info,
+                // файл закончился ровно на границе фрейма
+                Err(e) if e.is_eof() && self.reader.aligned_reader().consumed().is_empty() => {
+                    let offset = self.reader.aligned_reader().position();
+                    return Ok(self.end_of_stream(offset, false));
+                }
+                Err(e @ FlacError::Io { .. }) if !e.is_eof() => return Err(e),
+                Err(e) if self.recovery == Recovery::Fail => return Err(e),
+                Err(_) => {
+                    self.reader.byte_align();
+                    let crc_reader = self.reader.aligned_reader();
+                    let frame_offset = crc_reader.position() - crc_reader.consumed().len() as u64;
+                    corrupt_offset.get_or_insert(frame_offset);
+                    // сэмплы битого фрейма после seek отбрасывать уже не нужно
+                    self.skip_samples = 0;
+
+                    if !self.resync()? {
+                        // до конца потока фреймов больше нет
+                        return Ok(self.end_of_stream(frame_offset, true));
+                    }
+                }
+            }
+        };
+
+        if self.crc_mode == CrcMode::Strict && !info.crc_status.is_valid() {
+            return Err(FlacError::CrcMismatch {
+                status: info.crc_status,
+                offset: Offset::from_bytes(info.offset),
+            });
+        }
+
+        if !self.seeked {
+            self.hasher.update(&self.channels);
+        }
+
+        // после seek фрейм начинается раньше запрошенного сэмпла
+        if self.skip_samples > 0 {
+            let skip = usize::try_from(self.skip_samples)
+                .unwrap_or(usize::MAX)
+                .min(info.header.block_size as usize);
+            for channel in &mut self.channels {
+                channel.drain(..skip);
+            }
+            self.skip_samples = 0;
+        }
+
+        // номер фрейма дальше ожидаемого - фреймы потеряны или были битыми
+        let first_sample = info.header.first_sample(&self.stream_info);
+        let lost_samples = self
+            .next_sample
+            .map_or(0, |next_sample| first_sample.saturating_sub(next_sample));
+        self.next_sample = Some(first_sample + u64::from(info.header.block_size));
+        if corrupt_offset.is_some() || lost_samples > 0 {
+            let gap = Gap {
+                offset: corrupt_offset.unwrap_or(info.offset),
+                first_sample: first_sample - lost_samples,
+                lost_samples,
+            };
+            self.gaps.push(gap);
+
+            if self.recovery == Recovery::FillSilence && lost_samples > 0 {
+                // разрыв отдается с первым фреймом тишины, декодированный фрейм ждет
+                mem::swap(&mut self.channels, &mut self.pending_channels);
+                mem::swap(
+                    &mut self.subframe_headers,
+                    &mut self.pending_subframe_headers,
+                );
+                self.pending_frame = Some(info);
+                self.silence_start = gap.first_sample;
+                self.silence_samples = lost_samples;
+                return Ok(Some(self.next_silence(Some(gap))));
+            }
+
+            info.gap = Some(gap);
+            self.lost_samples += lost_samples;
+        }
+
+        self.decoded_samples += self.samples_per_channel() as u64;
+
+        Ok(Some(info))
+    }
+
+    // поток кончился: потеря до total_samples из STREAMINFO тоже разрыв,
+    // corrupt - перед концом были битые данные
+    fn end_of_stream(&mut self, offset: u64, corrupt: bool) -> Option<FrameInfo> {
+        let position = self.position();
+        let lost_samples = self.stream_info.total_samples.saturating_sub(position);
+        if !corrupt && lost_samples == 0 {
+            return None;
+        }
+
+        let gap = Gap {
+            offset,
+            first_sample: position,
+            lost_samples,
+        };
+        self.gaps.push(gap);
+
+        if self.recovery == Recovery::FillSilence && lost_samples > 0 {
+            self.silence_start = gap.first_sample;
+            self.silence_samples = lost_samples;
+            return Some(self.next_silence(Some(gap)));
+        }
+
+        self.lost_samples += lost_samples;
+        None
+    }
+
+    // номер следующего сэмпла потока
+    fn position(&self) -> u64 {
+        self.decoded_samples + self.lost_samples
+    }
+
+    // следующий фрейм тишины в буферы каналов: не длиннее max_block_size,
+    // чтобы поместиться в буферы вызывающего, как обычный фрейм
+    // заголовок собирается по STREAMINFO, байтов в потоке у тишины нет
+    fn next_silence(&mut self, gap: Option<Gap>) -> FrameInfo {
+        let max_block_size = self.stream_info.max_block_size.max(1);
+        let block_size = u16::try_from(self.silence_samples)
+            .map_or(max_block_size, |samples| samples.min(max_block_size));
+        let first_sample = self.silence_start;
+        self.silence_start += u64::from(block_size);
+        self.silence_samples -= u64::from(block_size);
+        self.decoded_samples += u64::from(block_size);
+
+        let channel_count = self.stream_info.channels;
+        self.channels
+            .resize_with(usize::from(channel_count), Vec::new);
+        for channel in &mut self.channels {
+            channel.clear();
+            channel.resize(usize::from(block_size), 0);
+        }
+        self.subframe_headers.clear();
+        self.subframe_headers.resize(
+            usize::from(channel_count),
+            SubframeHeader {
+                subframe_type: SubframeType::Constant,
+                wasted_bits: 0,
+            },
+        );
+
+        FrameInfo {
+            header: FrameHeader {
+                blocking_strategy: BlockingStrategy::Variable,
+                block_size: u32::from(block_size),
+                sample_rate: self.stream_info.sample_rate,
+                channel_assignment: ChannelAssignment::Independent(channel_count),
+                bit_depth: u32::from(self.stream_info.bps),
+                position: FramePosition::SampleNumber(first_sample),
+                crc8: 0,
+            },
+            crc16: 0,
+            crc_status: CrcStatus {
+                header_crc8: true,
+                frame_crc16: true,
+            },
+            // тишина стоит на месте последнего разрыва
+            offset: self.gaps.last().map_or(0, |gap| gap.offset),
+            length: 0,
+            gap,
+        }
+    }
+
+    // количество сэмплов на канал в буферах после последнего decode_frame(),
+    // после seek может быть меньше размера блока
+    #[must_use]
+    pub fn samples_per_channel(&self) -> usize {
+        self.channels.first().map_or(0, Vec::len)
+    }
+
+    // сэмплы канала из последнего decode_frame()
+    #[must_use]
+    pub fn channel(&self, channel: usize) -> &[i64] {
+        &self.channels[channel]
+    }
+
+    // следующий фрейм или None в конце потока
+    pub fn next_frame(&mut self) -> Result<Option<Frame>> {
+        let Some(info) = self.decode_frame()? else {
+            return Ok(None);
+        };
+
+        let subframes = self
+            .subframe_headers
+            .iter()
+            .zip(&self.channels)
+            .map(|(&subframe_header, samples)| Subframe {
+                subframe_header,
+                samples: samples.clone(),
+            })
+            .collect();
+
+        Ok(Some(Frame {
+            header: info.header,
+            subframes,
+            crc16: info.crc16,
+            crc_status: info.crc_status,
+            offset: info.offset,
+            length: info.length,
+            gap: info.gap,
+        }))
+    }
+
+    // следующий фрейм сэмплами вперемешку по каналам,
+    // нормализация по разрядности из STREAMINFO
+    pub fn next_interleaved<S: Sample>(&mut self) -> Result<Option<Vec<S>>> {
+        let bps = u32::from(self.stream_info.bps);
+        Ok(self.next_frame()?.map(|frame| frame.interleaved(bps)))
+    }
+
+    // следующий фрейм отдельным буфером на каждый канал
+    pub fn next_planar<S: Sample>(&mut self) -> Result<Option<Vec<Vec<S>>>> {
+        let bps = u32::from(self.stream_info.bps);
+        Ok(self.next_frame()?.map(|frame| frame.planar(bps)))
+    }
+
+    // следующий фрейм вперемешку по каналам в буфер вызывающего,
+    // out должен вмещать max_block_size * channels сэмплов
+    pub fn read_interleaved_into<S: Sample>(&mut self, out: &mut [S]) -> Result<Option<FrameInfo>> {
+        // размер проверяется до декодирования, чтобы фрейм не потерялся
+        self.check_buffer(out.len(), self.max_frame_samples())?;
+
+        let Some(info) = self.decode_frame()? else {
+            return Ok(None);
+        };
+
+        let needed = self.samples_per_channel() * self.channels.len();
+        sample::interleave(
+            &self.channels,
+            u32::from(self.stream_info.bps),
+            &mut out[..needed],
+        );
+
+        Ok(Some(info))
+    }
+
+    // следующий фрейм в буферы вызывающего, по одному на канал,
+    // каждый должен вмещать max_block_size сэмплов
+    pub fn read_planar_into<S: Sample>(
+        &mut self,
+        out: &mut [&mut [S]],
+    ) -> Result<Option<FrameInfo>> {
+        let channel_count = usize::from(self.stream_info.channels);
+        let max_block_size = usize::from(self.stream_info.max_block_size);
+        self.check_buffer(out.len(), channel_count)?;
+        for channel in out.iter() {
+            self.check_buffer(channel.len(), max_block_size)?;
+        }
+
+        let Some(info) = self.decode_frame()? else {
+            return Ok(None);
+        };
+
+        let block_size = self.samples_per_channel();
+        let bps = u32::from(self.stream_info.bps);
+        for (out, samples) in out.iter_mut().zip(&self.channels) {
+            sample::convert(samples, bps, &mut out[..block_size]);
+        }
+
+        Ok(Some(info))
+    }
+
+    // сколько сэмплов всех каналов может быть в одном фрейме
+    fn max_frame_samples(&self) -> usize {
+        usize::from(self.stream_info.max_block_size) * usize::from(self.stream_info.channels)
+    }
+
+    fn check_buffer(&mut self, length: usize, needed: usize) -> Result<()> {
+        if length < needed {
+            return Err(FlacError::BufferTooSmall {
+                needed,
+                offset: Offset::from_bytes(self.reader.aligned_reader().position()),
+            });
+        }
+        Ok(())
+    }
+
+    // проверка целостности всего потока, как flac -t
+    // имеет смысл после того как декодированы все фреймы
+    #[must_use]
+    pub fn md5_status(&self) -> Md5Status {
+        if self.seeked {
+            return Md5Status::Skipped;
+        }
+        md5_check::verify(&self.stream_info.checksum_combined, &self.hasher.finish())
+    }
+
+    // поиск следующего настоящего фрейма после битого: байты битого фрейма
+    // кроме первого возвращаются в поток и просматриваются заново, так что Seek не нужен
+    // ридер остается на начале найденного фрейма, false если поток кончился
+    fn resync(&mut self) -> Result<bool> {
+        let crc_reader = self.reader.aligned_reader();
+        let consumed = crc_reader.consumed().to_vec();
+        crc_reader.unread(consumed.get(1..).unwrap_or_default());
+
+        // 14 бит синхрокода, 0 reserved и бит стратегии: 0xFFF8 или 0xFFF9
+        let mut previous = 0u8;
+        loop {
+            self.reader.aligned_reader().clear();
+            let byte = match self.reader.read::<8, u8>() {
+                Ok(byte) => byte,
+                Err(e) if e.kind() == io::ErrorKind::UnexpectedEof => return Ok(false),
+                Err(e) => return Err(error::io(e, &mut self.reader)),
+            };
+            if previous != 0xFF || byte & 0xFE != 0xF8 {
+                previous = byte;
+                continue;
+            }
+
+            // синхрокод возвращается в поток и разбирается вместе с заголовком
+            self.reader.aligned_reader().unread(&[0xFF, byte]);
+            let header = match frame::probe_frame_header(&mut self.reader, &self.stream_info) {
+                Ok(header) => header,
+                Err(e) if e.is_eof() => return Ok(false),
+                Err(e) => return Err(e),
+            };
+            self.reader.byte_align();
+
+            let crc_reader = self.reader.aligned_reader();
+            let consumed = crc_reader.consumed().to_vec();
+            if header.is_some_and(|header| {
+                header.matches_stream(&self.stream_info) && self.plausible(&header)
+            }) {
+                // весь фрейм прочитает следующий read_frame_into
+                self.reader.aligned_reader().unread(&consumed);
+                return Ok(true);
+            }
+
+            // ложный синхрокод внутри данных, ищу со следующего байта
+            self.reader.aligned_reader().unread(&consumed[1..]);
+            previous = 0;
+        }
+    }
+
+    // фрейм не раньше ожидаемого и не за концом потока
+    fn plausible(&self, header: &FrameHeader) -> bool {
+        let first_sample = header.first_sample(&self.stream_info);
+        let total_samples = self.stream_info.total_samples;
+        first_sample >= self.next_sample.unwrap_or(0)
+            && (total_samples == 0 || first_sample < total_samples)
+    }
+}
+
+impl<R: Read + Seek> Decoder<R> {
+    // перемотка на сэмпл: прыжок к ближайшей предыдущей точке из SEEKTABLE,
+    // дальше бисекция по байтам если длина потока известна,
+    // затем фреймы читаются вперед до нужного и лишние сэмплы в его начале отбрасываются
+    // смещения считаются от начала R, поток должен начинаться в его нулевой позиции
+    pub fn seek(&mut self, sample: u64) -> Result<()> {
+        let total_samples = self.stream_info.total_samples;
+        if total_samples != 0 && sample >= total_samples {
+            return Err(FlacError::SeekOutOfRange {
+                sample,
+                offset: Offset::from_bytes(self.metadata.audio_offset),
+            });
+        }
+
+        // точка SEEKTABLE - непроверенные данные из файла: используется,
+        // только если ее смещение попадает внутрь аудио, иначе поиск от первого фрейма
+        let audio_offset = self.metadata.audio_offset;
+        let stream_len = self.stream_len()?;
+        let (point_sample, start) = self
+            .metadata
+            .seek_table()
+            .and_then(|seek_table| seek_table.nearest(sample))
+            .and_then(|point| {
+                audio_offset
+                    .checked_add(point.offset)
+                    .filter(|&offset| offset < stream_len)
+                    .map(|offset| (point.sample_number, offset))
+            })
+            .unwrap_or((0, audio_offset));
+
+        self.seeked = true;
+        self.skip_samples = 0;
+        // недоотданная тишина и фрейм за ней относятся к старой позиции
+        self.silence_samples = 0;
+        self.pending_frame = None;
+        // первый фрейм после перемотки начинается раньше запрошенного сэмпла
+        self.next_sample = None;
+
+        // точка могла указать на фрейм дальше своего номера сэмпла,
+        // тогда поиск повторяется от первого фрейма
+        if self.seek_from(sample, start, point_sample, stream_len)?
+            || (start != audio_offset && self.seek_from(sample, audio_offset, 0, stream_len)?)
+        {
+            return Ok(());
+        }
+
+        Err(FlacError::SeekOutOfRange {
+            sample,
+            offset: Offset::from_bytes(self.reader.aligned_reader().position()),
+        })
+    }
+
+    // поиск фрейма с сэмплом, начиная с фрейма start с первым сэмплом start_sample
+    // false, если первый же найденный фрейм начинается после сэмпла
+    fn seek_from(
+        &mut self,
+        sample: u64,
+        mut start: u64,
+        start_sample: u64,
+        stream_len: u64,
+    ) -> Result<bool> {
+        // без total_samples интерполировать не по чему, остается линейный поиск
+        if self.stream_info.total_samples != 0 {
+            start = self.bisect(sample, start, start_sample, stream_len)?;
+        }
+
+        self.jump_to(start)?;
+
+        // фреймы до нужного только разбираются, без проверок и MD5
+        loop {
+            let info = match frame::read_frame_into(
+                &mut self.reader,
+                &self.stream_info,
+                &mut self.channels,
+                &mut self.subframe_headers,
+            ) {
+                Ok(info) => info,
+                Err(e) if e.is_eof() => {
+                    return Err(FlacError::SeekOutOfRange {
+                        sample,
+                        offset: Offset::from_bytes(self.reader.aligned_reader().position()),
+                    });
+                }
+                Err(e) => return Err(e),
+            };
+
+            let first_sample = info.header.first_sample(&self.stream_info);
+            if first_sample > sample {
+                // сэмпла нет между началом поиска и этим фреймом
+                return Ok(false);
+            }
+            if sample < first_sample + u64::from(info.header.block_size) {
+                // возвращаюсь к началу фрейма, чтобы следующий decode_frame отдал его
+                // начиная с запрошенного сэмпла
+                self.jump_to(info.offset)?;
+                self.skip_samples = sample - first_sample;
+                self.decoded_samples = sample;
+                self.lost_samples = 0;
+                return Ok(true);
+            }
+        }
+    }
+
+    // длина всего потока в байтах, позиция ридера не меняется
+    fn stream_len(&mut self) -> Result<u64> {
+        let position = self.reader.aligned_reader().position();
+        self.reader
+            .aligned_reader()
+            .stream_len()
+            .map_err(|source| FlacError::Io {
+                source,
+                offset: Offset::from_bytes(position),
+            })
+    }
+
+    // бинарный поиск фрейма с сэмплом по байтовым смещениям
+    // начало интервала - известный фрейм, конец - конец потока;
+    // позиция внутри интервала интерполируется по номерам сэмплов
+    // возвращает смещение фрейма не дальше нужного, от которого дочитывать линейно
+    fn bisect(
+        &mut self,
+        sample: u64,
+        mut low_offset: u64,
+        mut low_sample: u64,
+        mut high_offset: u64,
+    ) -> Result<u64> {
+        let mut high_sample = self.stream_info.total_samples;
+
+        // на коротком интервале быстрее дочитать фреймы подряд
+        let linear_scan = match self.stream_info.max_frame_size {
+            0 => DEFAULT_LINEAR_SCAN,
+            max_frame_size => u64::from(max_frame_size) * 2,
+        };
+
+        while high_offset - low_offset > linear_scan && high_sample > low_sample {
+            let guess = low_offset.saturating_add(
+                u64::try_from(
+                    u128::from(sample - low_sample) * u128::from(high_offset - low_offset)
+                        / u128::from(high_sample - low_sample),
+                )
+                .unwrap_or(u64::MAX),
+            );
+            // поиск строго после начала интервала, иначе найдется тот же фрейм
+            let guess = guess.clamp(low_offset + 1, high_offset - 1);
+
+            let Some((frame_offset, header)) = self.find_frame(guess, high_offset)? else {
+                // между guess и концом интервала фреймов нет
+                high_offset = guess;
+                continue;
+            };
+
+            let first_sample = header.first_sample(&self.stream_info);
+            if first_sample > sample {
+                high_offset = frame_offset;
+                high_sample = first_sample;
+            } else if sample < first_sample + u64::from(header.block_size) {
+                // нужный сэмпл в этом фрейме
+                return Ok(frame_offset);
+            } else {
+                low_offset = frame_offset;
+                low_sample = first_sample;
+            }
+        }
+
+        Ok(low_offset)
+    }
+
+    // поиск первого настоящего фрейма в [offset, limit): синхрокод,
+    // разбор заголовка, совпадение CRC-8 и параметров с STREAMINFO
+    fn find_frame(&mut self, offset: u64, limit: u64) -> Result<Option<(u64, FrameHeader)>> {
+        let mut position = offset;
+
+        while position + 1 < limit {
+            self.jump_to(position)?;
+
+            // 14 бит синхрокода, 0 reserved и бит стратегии: 0xFFF8 или 0xFFF9
+            let mut previous = 0u8;
+            loop {
+                if position >= limit {
+                    return Ok(None);
+                }
+                let byte = match self.reader.read::<8, u8>() {
+                    Ok(byte) => byte,
+                    Err(e) if e.kind() == io::ErrorKind::UnexpectedEof => return Ok(None),
+                    Err(source) => {
+                        return Err(FlacError::Io {
+                            source,
+                            offset: Offset::from_bytes(position),
+                        });
+                    }
+                };
+                position += 1;
+
+                if previous == 0xFF && byte & 0xFE == 0xF8 {
+                    break;
+                }
+                previous = byte;
+            }
+
+            let candidate = position - 2;
+            self.jump_to(candidate)?;
+
+            match frame::probe_frame_header(&mut self.reader, &self.stream_info) {
+                Ok(Some(header)) if header.matches_stream(&self.stream_info) => {
+                    return Ok(Some((candidate, header)));
+                }
+                Ok(_) => {}
+                Err(e) if e.is_eof() => return Ok(None),
+                Err(e) => return Err(e),
+            }
+
+            // ложный синхрокод внутри данных, ищу дальше
+            position = candidate + 1;
+        }
+
+        Ok(None)
+    }
+
+    fn jump_to(&mut self, offset: u64) -> Result<()> {
+        // недочитанные биты прошлого фрейма больше не нужны
+        self.reader.byte_align();
+        self.reader
+            .aligned_reader()
+            .seek_to(offset)
+            .map_err(|source| FlacError::Io {
+                source,
+                offset: Offset::from_bytes(offset),
+            })
+    }
+}
+
+// итератор по фреймам, после первой ошибки останавливается:
+// позиция в потоке после нее неизвестна
+pub struct Frames<'a, R> {
+    decoder: &'a mut Decoder<R>,
+    failed: bool,
+}
+
+impl<R: Read> Iterator for Frames<'_, R> {
+    type Item = Result<Frame>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.failed {
+            return None;
+        }
+
+        let frame = self.decoder.next_frame();
+        self.failed = frame.is_err();
+        frame.transpose()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Cursor;
+    use std::ops::Range;
+
+    use super::*;
+    use crate::crc;
+    use crate::metedata_blocks::MetadataBlock;
+    use crate::seek_table::{SeekPoint, SeekTable};
+
+    const BLOCK_SIZE: u64 = 64;
+    const FRAMES: u64 = 40;
+    // заголовок 7 байт, сабфрейм 1 + 64 байта, CRC-16 2 байта
+    const FRAME_LENGTH: u32 = 74;
+
+    // у каждого сэмпла свое значение, по нему видно, откуда начался фрейм после seek
+    fn sample_value(sample: u64) -> i64 {
+        i64::try_from(sample % 200).unwrap() - 100
+    }
+
+    // точка SEEKTABLE на каждый десятый фрейм
+    fn seek_points() -> Vec<SeekPoint> {
+        (0..FRAMES)
+            .step_by(10)
+            .map(|frame| SeekPoint {
+                sample_number: frame * BLOCK_SIZE,
+                offset: frame * u64::from(FRAME_LENGTH),
+                frame_samples: 64,
+            })
+            .collect()
+    }
+
+    // моно, 8 бит, 44100 Гц, фиксированные блоки по 64 сэмпла в VERBATIM сабфреймах
+    // SEEKTABLE из seek_points, если они есть
+    fn test_stream(seek_points: Vec<SeekPoint>) -> Vec<u8> {
+        let stream_info =
+            StreamInfo::new(64, 64, 73, 73, 44100, 1, 8, BLOCK_SIZE * FRAMES, [0; 16]);
+        let blocks = if seek_points.is_empty() {
+            Vec::new()
+        } else {
+            vec![MetadataBlock::SeekTable(SeekTable {
+                points: seek_points,
+            })]
+        };
+
+        let mut bytes = metedata_blocks::metadata_to_bytes(&Metadata {
+            stream_info,
+            blocks,
+            audio_offset: 0,
+        })
+        .unwrap();
+
+        for frame in 0..FRAMES {
+            let start = bytes.len();
+            // синхрокод, блок 8 битами в конце заголовка и 44100 Гц,
+            // один канал и 8 бит, номер фрейма одним байтом, размер блока - 1
+            bytes.extend_from_slice(&[0xFF, 0xF8, 0x69, 0x02]);
+            bytes.push(u8::try_from(frame).unwrap());
+            bytes.push(u8::try_from(BLOCK_SIZE - 1).unwrap());
+            bytes.push(crc::crc8(&bytes[start..]));
+
+            // VERBATIM сабфрейм
+            bytes.push(0b0000_0010);
+            for sample in frame * BLOCK_SIZE..(frame + 1) * BLOCK_SIZE {
+                bytes.push(i8::try_from(sample_value(sample)).unwrap().to_be_bytes()[0]);
+            }
+
+            let crc16 = crc::crc16(&bytes[start..]);
+            bytes.extend_from_slice(&crc16.to_be_bytes());
+        }
+
+        bytes
+    }
+
+    #[test]
+    fn test_stream_decodes() {
+        let mut decoder = Decoder::new(Cursor::new(test_stream(Vec::new()))).unwrap();
+
+        let mut sample = 0;
+        while let Some(info) = decoder.decode_frame().unwrap() {
+            assert!(info.crc_status.is_valid());
+            assert_eq!(info.length, u64::from(FRAME_LENGTH));
+            for &value in decoder.channel(0) {
+                assert_eq!(value, sample_value(sample));
+                sample += 1;
+            }
+        }
+        assert_eq!(sample, BLOCK_SIZE * FRAMES);
+    }
+
+    #[test]
+    fn seek_to_frame_edges() {
+        // первый и последний сэмпл фреймов в начале, середине и конце потока,
+        // вперед и назад, на точке SEEKTABLE и между точками
+        let samples = [
+            0, 63, 64, 639, 640, 641, 1279, 2559, 1280, 127, 2496, 700, 1,
+        ];
+
+        for seek_points in [Vec::new(), seek_points()] {
+            let mut decoder = Decoder::new(Cursor::new(test_stream(seek_points))).unwrap();
+            for sample in samples {
+                assert_seek(&mut decoder, sample);
+            }
+        }
+    }
+
+    #[test]
+    fn seek_points_outside_stream_are_ignored() {
+        for offset in [1_000_000, u64::MAX - 10] {
+            let seek_points = vec![SeekPoint {
+                sample_number: 640,
+                offset,
+                frame_samples: 64,
+            }];
+            let mut decoder = Decoder::new(Cursor::new(test_stream(seek_points))).unwrap();
+            for sample in [0, 640, 700, 2559] {
+                assert_seek(&mut decoder, sample);
+            }
+        }
+    }
+
+    #[test]
+    fn wrong_seek_point_falls_back_to_first_frame() {
+        // точка обещает фрейм 5, а указывает на фрейм 6
+        let seek_points = vec![SeekPoint {
+            sample_number: 5 * BLOCK_SIZE,
+            offset: 6 * u64::from(FRAME_LENGTH),
+            frame_samples: 64,
+        }];
+        let mut decoder = Decoder::new(Cursor::new(test_stream(seek_points))).unwrap();
+        for sample in [330, 320, 383, 384, 1000] {
+            assert_seek(&mut decoder, sample);
+        }
+    }
+
+    // после seek декодирование идет ровно с sample, а следующий фрейм - за ним
+    fn assert_seek(decoder: &mut Decoder<Cursor<Vec<u8>>>, sample: u64) {
+        decoder.seek(sample).unwrap();
+
+        let info = decoder.decode_frame().unwrap().unwrap();
+        let first_sample = sample - sample % BLOCK_SIZE;
+        assert_eq!(
+            info.header.first_sample(decoder.stream_info()),
+            first_sample,
+            "sample {sample}"
+        );
+        assert_eq!(
+            decoder.samples_per_channel() as u64,
+            first_sample + BLOCK_SIZE - sample
+        );
+        assert_eq!(decoder.channel(0)[0], sample_value(sample));
+
+        let next_sample = first_sample + BLOCK_SIZE;
+        match decoder.decode_frame().unwrap() {
+            Some(_) => assert_eq!(decoder.channel(0)[0], sample_value(next_sample)),
+            None => assert_eq!(next_sample, BLOCK_SIZE * FRAMES),
+        }
+    }
+
+    #[test]
+    fn seek_past_end_is_an_error() {
+        let mut decoder = Decoder::new(Cursor::new(test_stream(seek_points()))).unwrap();
+        assert!(matches!(
+            decoder.seek(BLOCK_SIZE * FRAMES),
+            Err(FlacError::SeekOutOfRange { .. })
+        ));
+    }
+
+    // смещение фрейма в потоке из test_stream, отсчет от конца: перед аудио метаданные
+    fn frame_offset(bytes: &[u8], frame: u64) -> usize {
+        bytes.len() - usize::try_from((FRAMES - frame) * u64::from(FRAME_LENGTH)).unwrap()
+    }
+
+    // сэмплы всего потока подряд или первая ошибка
+    fn decode_all(decoder: &mut Decoder<Cursor<Vec<u8>>>) -> Result<Vec<i64>> {
+        let mut samples = Vec::new();
+        while decoder.decode_frame()?.is_some() {
+            samples.extend_from_slice(decoder.channel(0));
+        }
+        Ok(samples)
+    }
+
+    // сэмплы потока без потерянного диапазона или с тишиной на его месте
+    fn expected_samples(lost: Range<u64>, fill_silence: bool) -> Vec<i64> {
+        (0..BLOCK_SIZE * FRAMES)
+            .filter(|sample| fill_silence || !lost.contains(sample))
+            .map(|sample| {
+                if lost.contains(&sample) {
+                    0
+                } else {
+                    sample_value(sample)
+                }
+            })
+            .collect()
+    }
+
+    // поток с одним разрывом во всех режимах восстановления
+    // fails - Recovery::Fail останавливается на разрыве ошибкой
+    fn check_recovery(bytes: &[u8], gap: Gap, fails: bool) {
+        let lost = gap.first_sample..gap.first_sample + gap.lost_samples;
+
+        for recovery in [Recovery::Fail, Recovery::Skip, Recovery::FillSilence] {
+            let mut decoder = Decoder::new(Cursor::new(bytes.to_vec())).unwrap();
+            decoder.set_recovery(recovery);
+            let result = decode_all(&mut decoder);
+
+            if fails && recovery == Recovery::Fail {
+                assert!(result.is_err());
+                assert!(decoder.gaps().is_empty());
+                assert_eq!(decoder.decoded_samples(), gap.first_sample);
+                continue;
+            }
+
+            let fill_silence = recovery == Recovery::FillSilence;
+            let expected = expected_samples(lost.clone(), fill_silence);
+            assert_eq!(result.unwrap(), expected, "{recovery:?}");
+            assert_eq!(decoder.gaps(), [gap], "{recovery:?}");
+            assert_eq!(decoder.decoded_samples(), expected.len() as u64);
+            assert_eq!(
+                decoder.lost_samples(),
+                if fill_silence { 0 } else { gap.lost_samples }
+            );
+        }
+    }
+
+    #[test]
+    fn corrupt_sync_is_a_gap() {
+        let mut bytes = test_stream(Vec::new());
+        let offset = frame_offset(&bytes, 5);
+        bytes[offset] = 0;
+
+        let gap = Gap {
+            offset: offset as u64,
+            first_sample: 5 * BLOCK_SIZE,
+            lost_samples: BLOCK_SIZE,
+        };
+        check_recovery(&bytes, gap, true);
+    }
+
+    #[test]
+    fn dropped_frames_are_a_gap() {
+        // фреймы 10..13 вырезаны, поток цел, видно только по номерам фреймов
+        let mut bytes = test_stream(Vec::new());
+        let offset = frame_offset(&bytes, 10);
+        bytes.drain(offset..frame_offset(&bytes, 13));
+
+        let gap = Gap {
+            offset: offset as u64,
+            first_sample: 10 * BLOCK_SIZE,
+            lost_samples: 3 * BLOCK_SIZE,
+        };
+        check_recovery(&bytes, gap, false);
+    }
+
+    #[test]
+    fn truncated_tail_is_a_gap() {
+        // файл обрывается посреди фрейма 37
+        let mut bytes = test_stream(Vec::new());
+        let offset = frame_offset(&bytes, 37);
+        bytes.truncate(offset + 30);
+
+        let gap = Gap {
+            offset: offset as u64,
+            first_sample: 37 * BLOCK_SIZE,
+            lost_samples: 3 * BLOCK_SIZE,
+        };
+        check_recovery(&bytes, gap, true);
+    }
+}
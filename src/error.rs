@@ -0,0 +1,190 @@
+use std::error::Error;
+use std::fmt;
+use std::io::{self, Read, Seek};
+
+use bitstream_io::{BigEndian, BitReader};
+
+use crate::crc::CrcStatus;
+
+// место в потоке где произошла ошибка, считается от начала файла
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Offset {
+    pub byte: u64,
+    // бит внутри байта, 0 - старший
+    pub bit: u8,
+}
+
+impl Offset {
+    #[must_use]
+    pub fn from_bytes(byte: u64) -> Self {
+        Offset { byte, bit: 0 }
+    }
+
+    #[must_use]
+    pub fn from_bits(bits: u64) -> Self {
+        Offset {
+            byte: bits / 8,
+            #[allow(clippy::cast_possible_truncation)]
+            bit: (bits % 8) as u8,
+        }
+    }
+}
+
+impl fmt::Display for Offset {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.bit == 0 {
+            write!(f, "byte {}", self.byte)
+        } else {
+            write!(f, "byte {} bit {}", self.byte, self.bit)
+        }
+    }
+}
+
+#[derive(Debug)]
+pub enum FlacError {
+    Io {
+        source: io::Error,
+        offset: Offset,
+    },
+    // нет сигнатуры fLaC или синхрокода фрейма
+    InvalidSync {
+        offset: Offset,
+    },
+    // зарезервированное или запрещенное значение поля
+    Reserved {
+        field: &'static str,
+        offset: Offset,
+    },
+    // номер фрейма/сэмпла закодирован неправильным UTF-8
+    InvalidUtf8Number {
+        offset: Offset,
+    },
+    // offset указывает на начало фрейма
+    CrcMismatch {
+        status: CrcStatus,
+        offset: Offset,
+    },
+    // блок метаданных короче чем требуют его поля
+    TruncatedMetadata {
+        block_type: u8,
+        offset: Offset,
+    },
+    // валидный поток, который декодер не умеет разбирать
+    Unsupported {
+        feature: &'static str,
+        offset: Offset,
+    },
+    // значения, которые не могут встретиться в корректном потоке
+    InvalidData {
+        reason: &'static str,
+        offset: Offset,
+    },
+    // запрошенного сэмпла нет в потоке: он за концом или на месте пропущенных фреймов,
+    // offset - где закончился поиск
+    SeekOutOfRange {
+        sample: u64,
+        offset: Offset,
+    },
+    // буфер вызывающего меньше чем нужно для фрейма, offset - следующий фрейм
+    BufferTooSmall {
+        needed: usize,
+        offset: Offset,
+    },
+}
+
+impl FlacError {
+    // место ошибки
+    #[must_use]
+    pub fn offset(&self) -> Offset {
+        match self {
+            FlacError::Io { offset, .. }
+            | FlacError::InvalidSync { offset }
+            | FlacError::Reserved { offset, .. }
+            | FlacError::InvalidUtf8Number { offset }
+            | FlacError::CrcMismatch { offset, .. }
+            | FlacError::TruncatedMetadata { offset, .. }
+            | FlacError::Unsupported { offset, .. }
+            | FlacError::InvalidData { offset, .. }
+            | FlacError::SeekOutOfRange { offset, .. }
+            | FlacError::BufferTooSmall { offset, .. } => *offset,
+        }
+    }
+
+    // поток закончился посреди чтения
+    #[must_use]
+    pub fn is_eof(&self) -> bool {
+        matches!(self, FlacError::Io { source, .. } if source.kind() == io::ErrorKind::UnexpectedEof)
+    }
+}
+
+impl fmt::Display for FlacError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            FlacError::Io { source, offset } => write!(f, "I/O error at {offset}: {source}"),
+            FlacError::InvalidSync { offset } => write!(f, "Lost sync at {offset}"),
+            FlacError::Reserved { field, offset } => {
+                write!(f, "Reserved value of {field} at {offset}")
+            }
+            FlacError::InvalidUtf8Number { offset } => {
+                write!(f, "Invalid UTF-8 coded number at {offset}")
+            }
+            FlacError::CrcMismatch { status, offset } => {
+                write!(f, "CRC mismatch in frame at {offset}: {status:?}")
+            }
+            FlacError::TruncatedMetadata { block_type, offset } => {
+                write!(
+                    f,
+                    "Truncated metadata block (type {block_type}) at {offset}"
+                )
+            }
+            FlacError::Unsupported { feature, offset } => {
+                write!(f, "Unsupported {feature} at {offset}")
+            }
+            FlacError::InvalidData { reason, offset } => write!(f, "{reason} at {offset}"),
+            FlacError::SeekOutOfRange { sample, offset } => {
+                write!(f, "Sample {sample} is not in the stream at {offset}")
+            }
+            FlacError::BufferTooSmall { needed, offset } => {
+                write!(f, "Output buffer too small at {offset}: {needed} needed")
+            }
+        }
+    }
+}
+
+impl Error for FlacError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            FlacError::Io { source, .. } => Some(source),
+            _ => None,
+        }
+    }
+}
+
+// для Read адаптеров: ошибки чтения сохраняют свой вид, остальные - InvalidData
+impl From<FlacError> for io::Error {
+    fn from(error: FlacError) -> Self {
+        let kind = match &error {
+            FlacError::Io { source, .. } => source.kind(),
+            _ => io::ErrorKind::InvalidData,
+        };
+        io::Error::new(kind, error)
+    }
+}
+
+pub type Result<T> = std::result::Result<T, FlacError>;
+
+// текущая позиция битового ридера, ридер аудио фреймов сам считает прочитанные байты
+pub(crate) fn position<R: Read + Seek>(reader: &mut BitReader<R, BigEndian>) -> Offset {
+    Offset::from_bits(reader.position_in_bits().unwrap_or(0))
+}
+
+// ошибка чтения с позицией, на которой она случилась
+pub(crate) fn io<R: Read + Seek>(
+    source: io::Error,
+    reader: &mut BitReader<R, BigEndian>,
+) -> FlacError {
+    FlacError::Io {
+        source,
+        offset: position(reader),
+    }
+}
@@ -0,0 +1,631 @@
+use std::io::{Read, Seek};
+
+use bitstream_io::{BigEndian, BitRead, BitReader};
+
+use crate::crc::{self, CrcReader, CrcStatus};
+use crate::error::{self, FlacError, Offset, Result};
+use crate::sample::{self, Sample};
+use crate::stream_info::StreamInfo;
+use crate::subframe::{self, Subframe, SubframeHeader};
+
+// битовый ридер аудио фреймов, запоминающий прочитанные байты для CRC
+pub(crate) type FrameReader<R> = BitReader<CrcReader<R>, BigEndian>;
+
+// стратегия разбиения на блоки
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BlockingStrategy {
+    // все блоки одного размера, в заголовке номер фрейма
+    Fixed,
+    // размер блока может меняться, в заголовке номер первого сэмпла
+    Variable,
+}
+
+// позиция фрейма в потоке, зависит от стратегии разбиения
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FramePosition {
+    FrameNumber(u32),
+    SampleNumber(u64),
+}
+
+impl FramePosition {
+    // номер первого сэмпла фрейма
+    // для фиксированного размера блока нужен номинальный размер из STREAMINFO,
+    // потому что последний фрейм может быть короче
+    #[must_use]
+    pub fn first_sample(self, nominal_block_size: u32) -> u64 {
+        match self {
+            FramePosition::FrameNumber(number) => u64::from(number) * u64::from(nominal_block_size),
+            FramePosition::SampleNumber(number) => number,
+        }
+    }
+}
+
+// распределение каналов и режим межканальной декорреляции
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ChannelAssignment {
+    // от 1 до 8 независимых каналов
+    Independent(u8),
+    LeftSide,
+    SideRight,
+    MidSide,
+}
+
+impl ChannelAssignment {
+    #[must_use]
+    pub fn channels(self) -> u8 {
+        match self {
+            ChannelAssignment::Independent(channels) => channels,
+            ChannelAssignment::LeftSide
+            | ChannelAssignment::SideRight
+            | ChannelAssignment::MidSide => 2,
+        }
+    }
+
+    // индекс сабфрейма с side каналом, которому нужен дополнительный бит
+    #[must_use]
+    pub fn side_channel(self) -> Option<usize> {
+        match self {
+            ChannelAssignment::Independent(_) => None,
+            ChannelAssignment::LeftSide | ChannelAssignment::MidSide => Some(1),
+            ChannelAssignment::SideRight => Some(0),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct FrameHeader {
+    pub blocking_strategy: BlockingStrategy,
+    pub block_size: u32,
+    // в герцах
+    pub sample_rate: u32,
+    pub channel_assignment: ChannelAssignment,
+    pub bit_depth: u32,
+    pub position: FramePosition,
+    pub crc8: u8,
+}
+
+impl FrameHeader {
+    // номер первого сэмпла фрейма, по нему считаются точные временные метки
+    #[must_use]
+    pub fn first_sample(&self, stream_info: &StreamInfo) -> u64 {
+        self.position
+            .first_sample(u32::from(stream_info.max_block_size))
+    }
+
+    // заголовок согласуется с STREAMINFO: фрейм с другими параметрами не поместится
+    // в буферы декодера, а при поиске синхрокода это защита от случайного совпадения CRC-8
+    pub(crate) fn matches_stream(&self, stream_info: &StreamInfo) -> bool {
+        self.channel_assignment.channels() == stream_info.channels
+            && self.bit_depth == u32::from(stream_info.bps)
+            && self.sample_rate == stream_info.sample_rate
+            && self.block_size <= u32::from(stream_info.max_block_size)
+    }
+}
+
+pub struct Frame {
+    pub header: FrameHeader,
+    // по одному сабфрейму на канал, в порядке каналов
+    pub subframes: Vec<Subframe>,
+    pub crc16: u16,
+    pub crc_status: CrcStatus,
+    // смещение первого байта фрейма от начала файла
+    pub offset: u64,
+    // длина фрейма в байтах вместе с CRC-16
+    pub length: u64,
+    // битые или потерянные данные прямо перед фреймом
+    pub gap: Option<Gap>,
+}
+
+impl Frame {
+    // декодированные сэмплы одного канала
+    #[must_use]
+    pub fn channel(&self, channel: usize) -> &[i64] {
+        &self.subframes[channel].samples
+    }
+
+    // количество сэмплов на канал
+    #[must_use]
+    pub fn block_size(&self) -> u32 {
+        self.header.block_size
+    }
+
+    // сэмплы всех каналов вперемешку: L R L R ...
+    #[must_use]
+    pub fn interleaved<S: Sample>(&self, bps: u32) -> Vec<S> {
+        let channels: Vec<&[i64]> = self
+            .subframes
+            .iter()
+            .map(|subframe| subframe.samples.as_slice())
+            .collect();
+
+        // после seek первый фрейм может быть короче размера блока
+        let block_size = channels.first().map_or(0, |channel| channel.len());
+
+        let mut samples = vec![S::default(); block_size * channels.len()];
+        sample::interleave(&channels, bps, &mut samples);
+        samples
+    }
+
+    // каждый канал отдельным буфером
+    #[must_use]
+    pub fn planar<S: Sample>(&self, bps: u32) -> Vec<Vec<S>> {
+        self.subframes
+            .iter()
+            .map(|subframe| {
+                let mut samples = vec![S::default(); subframe.samples.len()];
+                sample::convert(&subframe.samples, bps, &mut samples);
+                samples
+            })
+            .collect()
+    }
+}
+
+// функция для чтения переменной длины UTF-8 закодированного u64
+fn read_utf8_u64<R: Read + Seek>(reader: &mut BitReader<R, BigEndian>) -> Result<u64> {
+    let offset = error::position(reader);
+    let mut val = u64::from(reader.read::<8, u8>().map_err(|e| error::io(e, reader))?);
+    let mut mask = 0x80;
+    let mut len = 0;
+
+    // определяем количество дополнительных байт по количеству ведущих единиц
+    while (val & mask) != 0 {
+        len += 1;
+        mask >>= 1;
+    }
+
+    if len == 1 || len > 7 {
+        return Err(FlacError::InvalidUtf8Number { offset });
+    }
+
+    if len == 0 {
+        return Ok(val); // число < 128
+    }
+
+    // оставляем только полезные биты из первого байта
+    val &= mask - 1;
+
+    for _ in 0..(len - 1) {
+        let byte = u64::from(reader.read::<8, u8>().map_err(|e| error::io(e, reader))?);
+        if (byte & 0xC0) != 0x80 {
+            return Err(FlacError::InvalidUtf8Number { offset });
+        }
+        val = (val << 6) | (byte & 0x3F);
+    }
+
+    Ok(val)
+}
+
+// чтение заголовка аудио фрейма
+// коды 0b0000 (sample rate) и 0b000 (bit depth) берут значения из STREAMINFO
+#[allow(clippy::too_many_lines)]
+fn read_frame_header<R: Read>(
+    reader: &mut FrameReader<R>,
+    stream_info: &StreamInfo,
+) -> Result<FrameHeader> {
+    // чтение синхронизирующего кода из аудио фрейма
+    // 14 бит (не 15!)
+    // всегда должно быть 0b11111111111110
+    let frame_offset = error::position(reader);
+    let sync_code = reader.read::<14, u16>().map_err(|e| error::io(e, reader))?;
+    if sync_code != 0x3FFE {
+        return Err(FlacError::InvalidSync {
+            offset: frame_offset,
+        });
+    }
+
+    // 1 бит - reserved
+    // должен быть 0
+    let _reserved = reader.read::<1, u8>().map_err(|e| error::io(e, reader))?;
+
+    // 1 бит
+    let blocking_strategy = if reader.read::<1, u8>().map_err(|e| error::io(e, reader))? == 0 {
+        BlockingStrategy::Fixed
+    } else {
+        BlockingStrategy::Variable
+    };
+
+    // 4 бита
+    let block_size_offset = error::position(reader);
+    let block_size_bits = reader.read::<4, u8>().map_err(|e| error::io(e, reader))?;
+
+    // обработка block_size
+    let mut block_size: u32 = match block_size_bits {
+        0b0000 => {
+            return Err(FlacError::Reserved {
+                field: "block size",
+                offset: block_size_offset,
+            });
+        }
+        0b0001 => 192,
+        0b0010..=0b0101 => 576 << (block_size_bits - 0b0010),
+        0b0110 | 0b0111 => 0, // будет прочитано позже
+        0b1000..=0b1111 => 1 << block_size_bits,
+        _ => unreachable!(),
+    };
+
+    // 4 бита - sample rate
+    let sample_rate_offset = error::position(reader);
+    let sample_rate_bits = reader.read::<4, u8>().map_err(|e| error::io(e, reader))?;
+
+    // обработка sample_rate, в герцах
+    let mut sample_rate: u32 = match sample_rate_bits {
+        0b0000 => stream_info.sample_rate, // взять из streaminfo
+        0b0001 => 88_200,
+        0b0010 => 176_400,
+        0b0011 => 192_000,
+        0b0100 => 8_000,
+        0b0101 => 16_000,
+        0b0110 => 22_050,
+        0b0111 => 24_000,
+        0b1000 => 32_000,
+        0b1001 => 44_100,
+        0b1010 => 48_000,
+        0b1011 => 96_000,
+        0b1100..=0b1110 => 0, // будет прочитано позже
+        0b1111 => {
+            return Err(FlacError::Reserved {
+                field: "sample rate",
+                offset: sample_rate_offset,
+            });
+        }
+        _ => unreachable!(),
+    };
+
+    // 4 бита - channel assignment
+    let channel_assignment_offset = error::position(reader);
+    let channel_assignment_bits = reader.read::<4, u8>().map_err(|e| error::io(e, reader))?;
+
+    // обработка channel_assignment
+    let channel_assignment = match channel_assignment_bits {
+        // 1 - mono, 2 - left, right ... 8 - 7.1
+        0b0000..=0b0111 => ChannelAssignment::Independent(channel_assignment_bits + 1),
+        0b1000 => ChannelAssignment::LeftSide,
+        0b1001 => ChannelAssignment::SideRight,
+        0b1010 => ChannelAssignment::MidSide,
+        0b1011..=0b1111 => {
+            return Err(FlacError::Reserved {
+                field: "channel assignment",
+                offset: channel_assignment_offset,
+            });
+        }
+        _ => unreachable!("Value from 4 bits cannot exceed 15"),
+    };
+
+    // 3 бита - bit depth
+    let bit_depth_offset = error::position(reader);
+    let bit_depth_bits = reader.read::<3, u8>().map_err(|e| error::io(e, reader))?;
+
+    // обработка bit_depth
+    let bit_depth = match bit_depth_bits {
+        0b000 => u32::from(stream_info.bps), // взять из streaminfo
+        0b001 => 8,
+        0b010 => 12,
+        0b011 => {
+            return Err(FlacError::Reserved {
+                field: "bit depth",
+                offset: bit_depth_offset,
+            });
+        }
+        0b100 => 16,
+        0b101 => 20,
+        0b110 => 24,
+        0b111 => 32,
+        _ => unreachable!(),
+    };
+
+    // 1 бит - mandatory (должен быть 0)
+    let _mandatory = reader.read::<1, u8>().map_err(|e| error::io(e, reader))?;
+
+    // чтение frame/sample number
+    // читаю из UTF-8 переменной длины
+    let coded_number = read_utf8_u64(reader)?;
+    let position = match blocking_strategy {
+        // номер фрейма занимает не больше 31 бита
+        BlockingStrategy::Fixed => match u32::try_from(coded_number) {
+            Ok(number) if number < 1 << 31 => FramePosition::FrameNumber(number),
+            _ => {
+                return Err(FlacError::InvalidData {
+                    reason: "Frame number out of range",
+                    offset: error::position(reader),
+                });
+            }
+        },
+        BlockingStrategy::Variable => FramePosition::SampleNumber(coded_number),
+    };
+
+    // дочитываем block_size если нужно
+    if block_size_bits == 0b0110 {
+        block_size = reader.read::<8, u32>().map_err(|e| error::io(e, reader))? + 1;
+    } else if block_size_bits == 0b0111 {
+        block_size = reader.read::<16, u32>().map_err(|e| error::io(e, reader))? + 1;
+    }
+
+    // дочитываю sample_rate если нужно
+    if sample_rate_bits == 0b1100 {
+        sample_rate = reader.read::<8, u32>().map_err(|e| error::io(e, reader))? * 1000; // хранится в файле как kHz
+    } else if sample_rate_bits == 0b1101 {
+        sample_rate = reader.read::<16, u32>().map_err(|e| error::io(e, reader))?; // хранится в файле как Hz
+    } else if sample_rate_bits == 0b1110 {
+        sample_rate = reader.read::<16, u32>().map_err(|e| error::io(e, reader))? * 10; // хранится в файле как Hz/10
+    }
+
+    // CRC-8
+    let crc8 = reader.read::<8, u8>().map_err(|e| error::io(e, reader))?;
+
+    Ok(FrameHeader {
+        blocking_strategy,
+        block_size,
+        sample_rate,
+        channel_assignment,
+        bit_depth,
+        position,
+        crc8,
+    })
+}
+
+// восстановление left/right из стерео режимов с межканальной декорреляцией
+fn decorrelate(channel_assignment: ChannelAssignment, channels: &mut [Vec<i64>]) {
+    let [first, second] = channels else {
+        return;
+    };
+
+    match channel_assignment {
+        // left-side: right = left - side
+        ChannelAssignment::LeftSide => {
+            for (left, side) in first.iter().zip(second.iter_mut()) {
+                *side = *left - *side;
+            }
+        }
+        // side-right: left = side + right
+        ChannelAssignment::SideRight => {
+            for (side, right) in first.iter_mut().zip(second.iter()) {
+                *side += *right;
+            }
+        }
+        // mid-side: младший бит mid потерян при делении, восстанавливаю его из side
+        ChannelAssignment::MidSide => {
+            for (mid, side) in first.iter_mut().zip(second.iter_mut()) {
+                let full_mid = (*mid << 1) | (*side & 1);
+                let left = (full_mid + *side) >> 1;
+                let right = (full_mid - *side) >> 1;
+                *mid = left;
+                *side = right;
+            }
+        }
+        // каналы хранятся независимо
+        ChannelAssignment::Independent(_) => {}
+    }
+}
+
+// проверка что с текущей позиции начинается фрейм: заголовок разбирается
+// и сходится по CRC-8; None для мусора, ошибка только при сбое чтения
+pub(crate) fn probe_frame_header<R: Read>(
+    reader: &mut FrameReader<R>,
+    stream_info: &StreamInfo,
+) -> Result<Option<FrameHeader>> {
+    reader.aligned_reader().clear();
+
+    let header = match read_frame_header(reader, stream_info) {
+        Ok(header) => header,
+        Err(e @ FlacError::Io { .. }) => return Err(e),
+        Err(_) => return Ok(None),
+    };
+
+    let header_bytes = reader.aligned_reader().consumed();
+    if crc::crc8(&header_bytes[..header_bytes.len() - 1]) == header.crc8 {
+        Ok(Some(header))
+    } else {
+        Ok(None)
+    }
+}
+
+// все про фрейм кроме самих сэмплов
+#[derive(Debug, Clone, Copy)]
+pub struct FrameInfo {
+    pub header: FrameHeader,
+    pub crc16: u16,
+    pub crc_status: CrcStatus,
+    // смещение первого байта фрейма от начала файла
+    pub offset: u64,
+    // длина фрейма в байтах вместе с CRC-16
+    pub length: u64,
+    // битые или потерянные данные прямо перед фреймом
+    pub gap: Option<Gap>,
+}
+
+// разрыв в потоке: битые данные, пропущенные при поиске следующего фрейма,
+// или фреймы, которых в потоке нет
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Gap {
+    // смещение первого пропущенного байта от начала файла
+    pub offset: u64,
+    // номер первого потерянного сэмпла
+    pub first_sample: u64,
+    // сколько сэмплов на канал потеряно, 0 если битыми были только лишние байты
+    pub lost_samples: u64,
+}
+
+// чтение и декодирование целого фрейма: заголовок, сабфреймы, паддинг и CRC-16
+// ридер должен стоять на границе байта в начале фрейма
+// сэмплы каналов пишутся в channels, заголовки сабфреймов в subframe_headers;
+// если емкости буферов хватает, память не выделяется
+pub(crate) fn read_frame_into<R: Read>(
+    reader: &mut FrameReader<R>,
+    stream_info: &StreamInfo,
+    channels: &mut Vec<Vec<i64>>,
+    subframe_headers: &mut Vec<SubframeHeader>,
+) -> Result<FrameInfo> {
+    // CRC считается с первого байта фрейма
+    reader.aligned_reader().clear();
+    let offset = reader.aligned_reader().position();
+
+    let header = read_frame_header(reader, stream_info)?;
+    // CRC-8 считается по всем байтам заголовка кроме последнего, где лежит сама сумма
+    let header_bytes = reader.aligned_reader().consumed();
+    let expected_crc8 = crc::crc8(&header_bytes[..header_bytes.len() - 1]);
+
+    if !header.matches_stream(stream_info) {
+        return Err(FlacError::InvalidData {
+            reason: "Frame header does not match STREAMINFO",
+            offset: Offset::from_bytes(offset),
+        });
+    }
+
+    let channel_assignment = header.channel_assignment;
+
+    // количество каналов (сабфреймов) берется из channel assignment
+    let channel_count = usize::from(channel_assignment.channels());
+
+    channels.resize_with(channel_count, Vec::new);
+    subframe_headers.clear();
+
+    for (channel, samples) in channels.iter_mut().enumerate() {
+        // side канал хранится с одним дополнительным битом точности
+        let subframe_bps = if channel_assignment.side_channel() == Some(channel) {
+            header.bit_depth + 1
+        } else {
+            header.bit_depth
+        };
+
+        samples.resize(header.block_size as usize, 0);
+        subframe_headers.push(subframe::decode_subframe_into(
+            reader,
+            subframe_bps,
+            samples,
+        )?);
+    }
+
+    decorrelate(channel_assignment, channels);
+
+    // после последнего сабфрейма идут нулевые биты до границы байта
+    while !reader.byte_aligned() {
+        if reader.read::<1, u8>().map_err(|e| error::io(e, reader))? != 0 {
+            return Err(FlacError::InvalidData {
+                reason: "Non-zero frame padding",
+                offset: error::position(reader),
+            });
+        }
+    }
+
+    // 16 бит - CRC-16 всего фрейма, считается по всем байтам до футера
+    let expected_crc16 = crc::crc16(reader.aligned_reader().consumed());
+    let crc16 = reader.read::<16, u16>().map_err(|e| error::io(e, reader))?;
+
+    let crc_status = CrcStatus {
+        header_crc8: header.crc8 == expected_crc8,
+        frame_crc16: crc16 == expected_crc16,
+    };
+
+    Ok(FrameInfo {
+        header,
+        crc16,
+        crc_status,
+        offset,
+        length: reader.aligned_reader().consumed().len() as u64,
+        gap: None,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Cursor;
+
+    use bitstream_io::{BitWrite, BitWriter};
+
+    use super::*;
+
+    const LEFT: [i64; 6] = [32767, -32768, 0, 1, -1, 12345];
+    const RIGHT: [i64; 6] = [-32768, 32767, 0, -1, 1, -3];
+
+    // один 16 битный стерео фрейм из 6 сэмплов, каналы пишет write_subframes
+    fn frame_bytes(
+        channel_bits: u8,
+        write_subframes: impl FnOnce(&mut BitWriter<Vec<u8>, BigEndian>),
+    ) -> Vec<u8> {
+        // блок 8 битами в конце заголовка, 44100 Гц, 16 бит, номер фрейма 0
+        let mut bytes = vec![0xFF, 0xF8, 0x69, channel_bits << 4 | 0b1000, 0x00, 5];
+        bytes.push(crc::crc8(&bytes));
+
+        let mut writer = BitWriter::endian(bytes, BigEndian);
+        write_subframes(&mut writer);
+        writer.byte_align().unwrap();
+        let mut bytes = writer.into_writer();
+
+        let crc16 = crc::crc16(&bytes);
+        bytes.extend_from_slice(&crc16.to_be_bytes());
+        bytes
+    }
+
+    fn verbatim(writer: &mut BitWriter<Vec<u8>, BigEndian>, bps: u32, samples: &[i64]) {
+        writer.write::<8, u8>(0b0000_0010).unwrap();
+        for &sample in samples {
+            writer.write_signed_var(bps, sample).unwrap();
+        }
+    }
+
+    fn read_frame(bytes: Vec<u8>) -> Result<Vec<Vec<i64>>> {
+        let stream_info = StreamInfo::new(16, 16, 0, 0, 44100, 2, 16, 0, [0; 16]);
+        let mut reader = BitReader::endian(CrcReader::new(Cursor::new(bytes), 0), BigEndian);
+        let mut channels = Vec::new();
+        let info = read_frame_into(&mut reader, &stream_info, &mut channels, &mut Vec::new())?;
+        assert!(info.crc_status.is_valid());
+        Ok(channels)
+    }
+
+    #[test]
+    fn left_side() {
+        let side: Vec<i64> = LEFT.iter().zip(RIGHT).map(|(l, r)| l - r).collect();
+        let bytes = frame_bytes(0b1000, |writer| {
+            verbatim(writer, 16, &LEFT);
+            verbatim(writer, 17, &side);
+        });
+        assert_eq!(read_frame(bytes).unwrap(), [LEFT, RIGHT]);
+    }
+
+    #[test]
+    fn side_right() {
+        let side: Vec<i64> = LEFT.iter().zip(RIGHT).map(|(l, r)| l - r).collect();
+        let bytes = frame_bytes(0b1001, |writer| {
+            verbatim(writer, 17, &side);
+            verbatim(writer, 16, &RIGHT);
+        });
+        assert_eq!(read_frame(bytes).unwrap(), [LEFT, RIGHT]);
+    }
+
+    #[test]
+    fn mid_side() {
+        // mid теряет младший бит, он восстанавливается по четности side
+        let mid: Vec<i64> = LEFT.iter().zip(RIGHT).map(|(l, r)| (l + r) >> 1).collect();
+        let side: Vec<i64> = LEFT.iter().zip(RIGHT).map(|(l, r)| l - r).collect();
+        let bytes = frame_bytes(0b1010, |writer| {
+            verbatim(writer, 16, &mid);
+            verbatim(writer, 17, &side);
+        });
+        assert_eq!(read_frame(bytes).unwrap(), [LEFT, RIGHT]);
+    }
+
+    #[test]
+    fn side_channel_out_of_range_is_invalid_data() {
+        // LPC порядка 1 с коэффициентом 2: второй сэмпл side выходит за 17 бит
+        let bytes = frame_bytes(0b1000, |writer| {
+            verbatim(writer, 16, &LEFT);
+            writer.write::<8, u8>(0b0100_0000).unwrap();
+            writer.write_signed::<17, i32>(65535).unwrap();
+            writer.write::<4, u8>(14).unwrap();
+            writer.write_signed::<5, i8>(0).unwrap();
+            writer.write_signed::<15, i16>(2).unwrap();
+            // escape партиция с нулевыми остатками
+            writer.write::<2, u8>(0b00).unwrap();
+            writer.write::<4, u8>(0).unwrap();
+            writer.write::<4, u8>(0b1111).unwrap();
+            writer.write::<5, u8>(0).unwrap();
+        });
+        assert!(matches!(
+            read_frame(bytes),
+            Err(FlacError::InvalidData {
+                reason: "Predicted sample exceeds subframe bit depth",
+                ..
+            })
+        ));
+    }
+}
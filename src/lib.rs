@@ -0,0 +1,41 @@
+#![warn(clippy::all, clippy::pedantic)]
+// комментарии в коде обычные, не rustdoc
+#![allow(clippy::missing_errors_doc, clippy::missing_panics_doc)]
+
+// docs : https://www.rfc-editor.org/rfc/rfc9639.html#name-examples
+
+pub mod application;
+pub mod crc;
+pub mod cue_sheet;
+pub mod decoder;
+pub mod error;
+pub mod frame;
+pub mod md5_check;
+pub mod metadata_editor;
+pub mod metedata_blocks;
+pub mod pcm_reader;
+pub mod picture;
+pub mod sample;
+pub mod seek_table;
+pub mod stream_info;
+pub mod subframe;
+pub mod vorbis_comment;
+
+pub use crate::application::{
+    Application, ApplicationContent, ApplicationParser, ApplicationRegistry, ForeignChunk,
+    ForeignFormat, ForeignMetadata,
+};
+pub use crate::crc::{CrcMode, CrcStatus};
+pub use crate::cue_sheet::{CueIndex, CueSheet, CueTrack};
+pub use crate::decoder::{Decoder, Frames, Recovery};
+pub use crate::error::{FlacError, Offset};
+pub use crate::frame::{Frame, FrameHeader, FrameInfo, Gap};
+pub use crate::md5_check::Md5Status;
+pub use crate::metadata_editor::{MetadataEditor, SaveMode};
+pub use crate::metedata_blocks::{Metadata, MetadataBlock};
+pub use crate::pcm_reader::PcmReader;
+pub use crate::picture::PictureBlock;
+pub use crate::sample::Sample;
+pub use crate::seek_table::{SeekPoint, SeekTable};
+pub use crate::stream_info::StreamInfo;
+pub use crate::vorbis_comment::VorbisComment;
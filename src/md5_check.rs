@@ -0,0 +1,71 @@
+// MD5 несжатого аудио из STREAMINFO:
+// сэмплы идут вперемешку по каналам (interleaved), каждый сэмпл записан
+// в little-endian и занимает ceil(bps / 8) байт
+
+// результат сравнения с суммой из STREAMINFO
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Md5Status {
+    Match,
+    Mismatch,
+    // энкодер записал нули, сравнивать не с чем
+    NotPresent,
+    // был seek, декодировано не все аудио
+    Skipped,
+}
+
+pub struct StreamHasher {
+    context: md5::Context,
+    bytes_per_sample: usize,
+    // буфер одного фрейма, чтобы не кормить MD5 по одному сэмплу
+    buffer: Vec<u8>,
+}
+
+impl StreamHasher {
+    #[must_use]
+    pub fn new(bps: u8) -> Self {
+        StreamHasher {
+            context: md5::Context::new(),
+            bytes_per_sample: usize::from(bps).div_ceil(8),
+            buffer: Vec::new(),
+        }
+    }
+
+    // заранее выделяет буфер под фрейм из samples сэмплов всех каналов
+    pub fn reserve(&mut self, samples: usize) {
+        self.buffer.reserve(samples * self.bytes_per_sample);
+    }
+
+    // добавляет в сумму декодированный фрейм, каналы уже после декорреляции
+    pub fn update<C: AsRef<[i64]>>(&mut self, channels: &[C]) {
+        let block_size = channels.first().map_or(0, |channel| channel.as_ref().len());
+
+        self.buffer.clear();
+        for i in 0..block_size {
+            for channel in channels {
+                let bytes = channel.as_ref()[i].to_le_bytes();
+                self.buffer
+                    .extend_from_slice(&bytes[..self.bytes_per_sample]);
+            }
+        }
+
+        self.context.consume(&self.buffer);
+    }
+
+    // сумма всего что добавлено на данный момент, сам контекст не трогаю
+    #[must_use]
+    pub fn finish(&self) -> [u8; 16] {
+        self.context.clone().finalize().into()
+    }
+}
+
+// сравнение посчитанной суммы с записанной в STREAMINFO
+#[must_use]
+pub fn verify(expected: &[u8; 16], computed: &[u8; 16]) -> Md5Status {
+    if expected.iter().all(|&byte| byte == 0) {
+        Md5Status::NotPresent
+    } else if expected == computed {
+        Md5Status::Match
+    } else {
+        Md5Status::Mismatch
+    }
+}
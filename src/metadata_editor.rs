@@ -0,0 +1,372 @@
+use std::ffi::OsString;
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, BufReader, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+
+use crate::error::{FlacError, Offset, Result};
+use crate::metedata_blocks::{self, MAX_BLOCK_LENGTH, Metadata, MetadataBlock};
+use crate::picture::PictureBlock;
+use crate::vorbis_comment::VorbisComment;
+
+// PADDING после полной перезаписи, чтобы следующие правки поместились на месте
+// столько же оставляет flac по умолчанию
+const DEFAULT_PADDING: u32 = 8192;
+
+// как были записаны метаданные
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SaveMode {
+    // поверх старых, за счет PADDING, аудио не тронуто
+    InPlace,
+    // файл переписан целиком через временный файл
+    Rewritten,
+}
+
+// правка метаданных FLAC файла
+// изменения копятся в памяти и пишутся в файл только в save()
+pub struct MetadataEditor {
+    path: PathBuf,
+    metadata: Metadata,
+}
+
+impl MetadataEditor {
+    pub fn open<P: AsRef<Path>>(path: P) -> Result<Self> {
+        let path = path.as_ref().to_path_buf();
+        let metadata = read_file_metadata(&path)?;
+        Ok(MetadataEditor { path, metadata })
+    }
+
+    #[must_use]
+    pub fn metadata(&self) -> &Metadata {
+        &self.metadata
+    }
+
+    // любые правки блоков, STREAMINFO лучше не трогать: он описывает аудио
+    pub fn metadata_mut(&mut self) -> &mut Metadata {
+        &mut self.metadata
+    }
+
+    // теги для правки, если блока нет - создается пустой
+    // неразобранный VORBIS_COMMENT - ошибка: замена пустым стерла бы теги в файле,
+    // а второй такой блок недопустим; сам блок с исходной ошибкой остается в metadata()
+    pub fn tags_mut(&mut self) -> Result<&mut VorbisComment> {
+        let position = self
+            .metadata
+            .blocks
+            .iter()
+            .position(|block| block.block_type() == 4);
+
+        let position = position.unwrap_or_else(|| {
+            self.metadata
+                .blocks
+                .push(MetadataBlock::VorbisComment(VorbisComment {
+                    vendor: concat!("flac-decoder ", env!("CARGO_PKG_VERSION")).to_string(),
+                    comments: Vec::new(),
+                    unparsed: Vec::new(),
+                }));
+            self.metadata.blocks.len() - 1
+        });
+
+        match &mut self.metadata.blocks[position] {
+            MetadataBlock::VorbisComment(tags) => Ok(tags),
+            MetadataBlock::Invalid { error, .. } => Err(FlacError::InvalidData {
+                reason: "VORBIS_COMMENT block could not be parsed",
+                offset: error.offset(),
+            }),
+            _ => unreachable!(),
+        }
+    }
+
+    pub fn add_picture(&mut self, picture: PictureBlock) {
+        self.metadata.blocks.push(MetadataBlock::Picture(picture));
+    }
+
+    // удаляет все картинки этого типа, 3 - обложка
+    pub fn remove_pictures(&mut self, picture_type: u32) {
+        self.metadata.blocks.retain(|block| match block {
+            MetadataBlock::Picture(picture) => picture.picture_type != picture_type,
+            _ => true,
+        });
+    }
+
+    // запись метаданных: поверх старых, если новые помещаются вместе с PADDING,
+    // иначе полная перезапись, аудио фреймы копируются без изменений
+    pub fn save(&mut self) -> Result<SaveMode> {
+        // файл мог измениться после open, место под метаданные смотрю заново
+        let available = read_file_metadata(&self.path)?.audio_offset;
+
+        // старый PADDING не нужен, свободное место посчитается заново
+        let mut metadata = self.metadata.clone();
+        metadata
+            .blocks
+            .retain(|block| !matches!(block, MetadataBlock::Padding(_)));
+        let length = metedata_blocks::metadata_to_bytes(&metadata)?.len() as u64;
+
+        // на месте: либо длина совпадает, либо остаток влезает в PADDING вместе с заголовком
+        let fits = length == available || {
+            let padding = available
+                .checked_sub(length + 4)
+                .and_then(|padding| u32::try_from(padding).ok())
+                .filter(|&padding| padding <= MAX_BLOCK_LENGTH);
+            if let Some(padding) = padding {
+                metadata.blocks.push(MetadataBlock::Padding(padding));
+            }
+            padding.is_some()
+        };
+
+        let mode = if fits {
+            let bytes = metedata_blocks::metadata_to_bytes(&metadata)?;
+            write_in_place(&self.path, &bytes).map_err(io_error)?;
+            SaveMode::InPlace
+        } else {
+            metadata
+                .blocks
+                .push(MetadataBlock::Padding(DEFAULT_PADDING));
+            let bytes = metedata_blocks::metadata_to_bytes(&metadata)?;
+            rewrite(&self.path, &bytes, available).map_err(io_error)?;
+            SaveMode::Rewritten
+        };
+
+        metadata.audio_offset = metedata_blocks::metadata_to_bytes(&metadata)?.len() as u64;
+        self.metadata = metadata;
+
+        Ok(mode)
+    }
+}
+
+fn read_file_metadata(path: &Path) -> Result<Metadata> {
+    let file = File::open(path).map_err(io_error)?;
+    metedata_blocks::read_metadata(&mut BufReader::new(file))
+}
+
+// метаданные той же длины поверх старых
+fn write_in_place(path: &Path, bytes: &[u8]) -> io::Result<()> {
+    let mut file = OpenOptions::new().write(true).open(path)?;
+    file.write_all(bytes)?;
+    file.sync_all()
+}
+
+// новый файл рядом со старым и переименование поверх него:
+// при сбое на любом шаге исходный файл остается целым
+fn rewrite(path: &Path, bytes: &[u8], audio_offset: u64) -> io::Result<()> {
+    let mut temp_name = OsString::from(".");
+    temp_name.push(path.file_name().unwrap_or_default());
+    temp_name.push(".tmp");
+    let temp_path = path.with_file_name(temp_name);
+
+    let result = (|| {
+        let mut source = File::open(path)?;
+        let mut temp = File::create(&temp_path)?;
+        temp.set_permissions(source.metadata()?.permissions())?;
+
+        temp.write_all(bytes)?;
+        source.seek(SeekFrom::Start(audio_offset))?;
+        io::copy(&mut source, &mut temp)?;
+        // данные должны быть на диске до rename
+        temp.sync_all()?;
+
+        fs::rename(&temp_path, path)
+    })();
+
+    if result.is_err() {
+        let _ = fs::remove_file(&temp_path);
+    }
+    result
+}
+
+fn io_error(source: io::Error) -> FlacError {
+    FlacError::Io {
+        source,
+        offset: Offset::from_bytes(0),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{env, process};
+
+    use super::*;
+    use crate::stream_info::StreamInfo;
+
+    // после метаданных байты, которые не похожи на фреймы:
+    // редактор аудио не разбирает, только копирует
+    fn test_audio() -> Vec<u8> {
+        (0..=255).cycle().take(3000).collect()
+    }
+
+    // FLAC файл во временном каталоге с тегом TITLE=Old и PADDING, удаляется в конце теста
+    struct TestFile(PathBuf);
+
+    impl TestFile {
+        fn new(name: &str, padding: Option<u32>) -> Self {
+            let mut blocks = vec![MetadataBlock::VorbisComment(VorbisComment {
+                vendor: "test".to_string(),
+                comments: vec![("TITLE".to_string(), "Old".to_string())],
+                unparsed: Vec::new(),
+            })];
+            blocks.extend(padding.map(MetadataBlock::Padding));
+            Self::with_blocks(name, blocks)
+        }
+
+        fn with_blocks(name: &str, blocks: Vec<MetadataBlock>) -> Self {
+            let metadata = Metadata {
+                stream_info: StreamInfo::new(4096, 4096, 0, 0, 44100, 2, 16, 0, [0; 16]),
+                blocks,
+                audio_offset: 0,
+            };
+
+            let mut bytes = metedata_blocks::metadata_to_bytes(&metadata).unwrap();
+            bytes.extend(test_audio());
+
+            let path = env::temp_dir().join(format!("flac-decoder-{}-{name}.flac", process::id()));
+            fs::write(&path, bytes).unwrap();
+            TestFile(path)
+        }
+
+        fn bytes(&self) -> Vec<u8> {
+            fs::read(&self.0).unwrap()
+        }
+
+        // метаданные и аудио, как их увидит следующий open
+        fn contents(&self) -> (Metadata, Vec<u8>) {
+            let bytes = self.bytes();
+            let metadata = metedata_blocks::read_metadata(&mut bytes.as_slice()).unwrap();
+            let audio = bytes[usize::try_from(metadata.audio_offset).unwrap()..].to_vec();
+            (metadata, audio)
+        }
+    }
+
+    impl Drop for TestFile {
+        fn drop(&mut self) {
+            let _ = fs::remove_file(&self.0);
+        }
+    }
+
+    #[test]
+    fn unchanged_metadata_is_saved_in_place() {
+        let file = TestFile::new("unchanged", None);
+        let before = file.bytes();
+
+        let mut editor = MetadataEditor::open(&file.0).unwrap();
+        assert_eq!(editor.save().unwrap(), SaveMode::InPlace);
+
+        assert_eq!(file.bytes(), before);
+    }
+
+    #[test]
+    fn small_edit_uses_padding() {
+        let file = TestFile::new("padding", Some(100));
+        let length = file.bytes().len();
+
+        let mut editor = MetadataEditor::open(&file.0).unwrap();
+        // "TITLE=Old" -> "TITLE=New title", на 6 байт длиннее
+        editor
+            .tags_mut()
+            .unwrap()
+            .set("title", "New title")
+            .unwrap();
+        assert_eq!(editor.save().unwrap(), SaveMode::InPlace);
+
+        let (metadata, audio) = file.contents();
+        assert_eq!(file.bytes().len(), length);
+        assert_eq!(metadata.padding().collect::<Vec<_>>(), [94]);
+        assert_eq!(metadata.tags().unwrap().get("TITLE"), Some("New title"));
+        assert_eq!(metadata.audio_offset, editor.metadata().audio_offset);
+        assert_eq!(audio, test_audio());
+    }
+
+    #[test]
+    fn save_mode_depends_on_free_space() {
+        // рост метаданных при PADDING в 100 байт: вместе с заголовком свободно 104,
+        // при росте на 101..=103 байта остаток не вмещает заголовок нового PADDING
+        let cases = [
+            (96, SaveMode::InPlace, vec![4]),
+            (100, SaveMode::InPlace, vec![0]),
+            (102, SaveMode::Rewritten, vec![DEFAULT_PADDING]),
+            (104, SaveMode::InPlace, vec![]),
+            (105, SaveMode::Rewritten, vec![DEFAULT_PADDING]),
+        ];
+
+        for (growth, mode, padding) in cases {
+            let file = TestFile::new(&format!("growth-{growth}"), Some(100));
+            let length = file.bytes().len();
+
+            // новое поле занимает 4 байта длины и "COMMENT=..."
+            let value = "x".repeat(growth - 4 - "COMMENT=".len());
+            let mut editor = MetadataEditor::open(&file.0).unwrap();
+            editor.tags_mut().unwrap().add("COMMENT", &value).unwrap();
+            assert_eq!(editor.save().unwrap(), mode, "growth {growth}");
+
+            let (metadata, audio) = file.contents();
+            assert_eq!(metadata.padding().collect::<Vec<_>>(), padding);
+            assert_eq!(
+                metadata.tags().unwrap().get("COMMENT"),
+                Some(value.as_str())
+            );
+            assert_eq!(metadata.audio_offset, editor.metadata().audio_offset);
+            assert_eq!(audio, test_audio());
+            if mode == SaveMode::InPlace {
+                assert_eq!(file.bytes().len(), length);
+            }
+        }
+    }
+
+    #[test]
+    fn saves_after_rewrite_use_new_padding() {
+        let file = TestFile::new("rewrite", None);
+
+        let mut editor = MetadataEditor::open(&file.0).unwrap();
+        editor.tags_mut().unwrap().add("ARTIST", "Someone").unwrap();
+        assert_eq!(editor.save().unwrap(), SaveMode::Rewritten);
+
+        editor.tags_mut().unwrap().remove("ARTIST");
+        assert_eq!(editor.save().unwrap(), SaveMode::InPlace);
+
+        let (metadata, audio) = file.contents();
+        // поле "ARTIST=Someone" занимало 4 + 14 байт
+        assert_eq!(
+            metadata.padding().collect::<Vec<_>>(),
+            [DEFAULT_PADDING + 18]
+        );
+        assert_eq!(metadata.tags().unwrap().get("ARTIST"), None);
+        assert_eq!(audio, test_audio());
+    }
+
+    #[test]
+    fn invalid_field_name_is_an_error() {
+        let file = TestFile::new("invalid-name", None);
+
+        let mut editor = MetadataEditor::open(&file.0).unwrap();
+        assert!(editor.tags_mut().unwrap().add("BAD=NAME", "value").is_err());
+        assert!(editor.tags_mut().unwrap().set("", "value").is_err());
+        assert_eq!(editor.tags_mut().unwrap().iter().count(), 1);
+    }
+
+    #[test]
+    fn unparsed_tags_are_not_replaced() {
+        // вендор длиннее блока
+        let mut data = 100u32.to_le_bytes().to_vec();
+        data.extend_from_slice(b"test");
+        let file = TestFile::with_blocks(
+            "unparsed-tags",
+            vec![MetadataBlock::Unknown {
+                block_type: 4,
+                data: data.clone(),
+            }],
+        );
+        let before = file.bytes();
+
+        let mut editor = MetadataEditor::open(&file.0).unwrap();
+        assert!(matches!(
+            editor.tags_mut(),
+            Err(FlacError::InvalidData { .. })
+        ));
+        assert_eq!(editor.save().unwrap(), SaveMode::InPlace);
+
+        assert_eq!(file.bytes(), before);
+        let (metadata, _) = file.contents();
+        assert!(matches!(
+            &metadata.blocks[..],
+            [MetadataBlock::Invalid { block_type: 4, data: saved, .. }] if *saved == data
+        ));
+    }
+}
@@ -1,9 +1,256 @@
-use std::{fs::File, io::{self, Read, Seek, SeekFrom}};
+use std::io::{self, Read, Write};
+use std::sync::Arc;
 
+use crate::application::Application;
+use crate::cue_sheet::CueSheet;
+use crate::error::{FlacError, Offset, Result};
+use crate::picture::PictureBlock;
+use crate::seek_table::SeekTable;
+use crate::stream_info::StreamInfo;
+use crate::vorbis_comment::VorbisComment;
 
-pub fn get_header(file: &mut File) -> Result<(bool, u8, u32), std::io::Error> {
+// длина содержимого блока хранится в 24 битах
+pub const MAX_BLOCK_LENGTH: u32 = 0xFF_FFFF;
+
+// заголовок блока метаданных
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct MetadataBlockHeader {
+    pub is_last: bool,
+    pub block_type: u8,
+    pub length: u32,
+}
+
+impl MetadataBlockHeader {
+    #[must_use]
+    pub fn to_bytes(&self) -> [u8; 4] {
+        let length = self.length.to_be_bytes();
+        [
+            u8::from(self.is_last) << 7 | (self.block_type & 0x7F),
+            length[1],
+            length[2],
+            length[3],
+        ]
+    }
+}
+
+// метаданные потока: STREAMINFO и остальные блоки в порядке файла
+#[derive(Debug, Clone)]
+pub struct Metadata {
+    pub stream_info: StreamInfo,
+    // блоки после STREAMINFO
+    pub blocks: Vec<MetadataBlock>,
+    // смещение первого аудио фрейма от начала файла
+    pub audio_offset: u64,
+}
+
+// разобранный блок метаданных
+#[derive(Debug, Clone)]
+pub enum MetadataBlock {
+    // размер PADDING в байтах, содержимое всегда нули
+    Padding(u32),
+    Application(Application),
+    SeekTable(SeekTable),
+    VorbisComment(VorbisComment),
+    CueSheet(CueSheet),
+    Picture(PictureBlock),
+    // блоки типов, которые декодер не знает, хранятся как есть
+    // и записываются обратно без изменений
+    Unknown {
+        block_type: u8,
+        data: Vec<u8>,
+    },
+    // блок известного типа, который не разобрался: байты как в Unknown и ошибка разбора
+    // Arc, потому что FlacError не клонируется из-за io::Error
+    Invalid {
+        block_type: u8,
+        data: Vec<u8>,
+        error: Arc<FlacError>,
+    },
+}
+
+impl MetadataBlock {
+    #[must_use]
+    pub fn block_type(&self) -> u8 {
+        match self {
+            MetadataBlock::Padding(_) => 1,
+            MetadataBlock::Application(_) => 2,
+            MetadataBlock::SeekTable(_) => 3,
+            MetadataBlock::VorbisComment(_) => 4,
+            MetadataBlock::CueSheet(_) => 5,
+            MetadataBlock::Picture(_) => 6,
+            MetadataBlock::Unknown { block_type, .. }
+            | MetadataBlock::Invalid { block_type, .. } => *block_type,
+        }
+    }
+
+    // содержимое блока без заголовка
+    #[must_use]
+    pub fn to_bytes(&self) -> Vec<u8> {
+        match self {
+            MetadataBlock::Padding(length) => vec![0; *length as usize],
+            MetadataBlock::Application(application) => application.to_bytes(),
+            MetadataBlock::SeekTable(seek_table) => seek_table.to_bytes(),
+            MetadataBlock::VorbisComment(tags) => tags.to_bytes(),
+            MetadataBlock::CueSheet(cue_sheet) => cue_sheet.to_bytes(),
+            MetadataBlock::Picture(picture) => picture.to_bytes(),
+            MetadataBlock::Unknown { data, .. } | MetadataBlock::Invalid { data, .. } => {
+                data.clone()
+            }
+        }
+    }
+}
+
+impl Metadata {
+    // размеры всех блоков PADDING
+    pub fn padding(&self) -> impl Iterator<Item = u32> {
+        self.blocks.iter().filter_map(|block| match block {
+            MetadataBlock::Padding(length) => Some(*length),
+            _ => None,
+        })
+    }
+
+    pub fn applications(&self) -> impl Iterator<Item = &Application> {
+        self.blocks.iter().filter_map(|block| match block {
+            MetadataBlock::Application(application) => Some(application),
+            _ => None,
+        })
+    }
+
+    // SEEKTABLE в потоке может быть только один
+    #[must_use]
+    pub fn seek_table(&self) -> Option<&SeekTable> {
+        self.blocks.iter().find_map(|block| match block {
+            MetadataBlock::SeekTable(seek_table) => Some(seek_table),
+            _ => None,
+        })
+    }
+
+    // VORBIS_COMMENT в потоке может быть только один
+    #[must_use]
+    pub fn tags(&self) -> Option<&VorbisComment> {
+        self.blocks.iter().find_map(|block| match block {
+            MetadataBlock::VorbisComment(tags) => Some(tags),
+            _ => None,
+        })
+    }
+
+    #[must_use]
+    pub fn cue_sheet(&self) -> Option<&CueSheet> {
+        self.blocks.iter().find_map(|block| match block {
+            MetadataBlock::CueSheet(cue_sheet) => Some(cue_sheet),
+            _ => None,
+        })
+    }
+
+    pub fn pictures(&self) -> impl Iterator<Item = &PictureBlock> {
+        self.blocks.iter().filter_map(|block| match block {
+            MetadataBlock::Picture(picture) => Some(picture),
+            _ => None,
+        })
+    }
+}
+
+// чтение сигнатуры и всех блоков метаданных, ридер остается на первом фрейме
+pub fn read_metadata<R: Read>(reader: &mut R) -> Result<Metadata> {
+    check_flac_header(reader)?;
+
+    // первый всегда идет STREAMINFO, сразу за сигнатурой
+    let streaminfo_header = get_header(reader, 4)?;
+    let stream_info = StreamInfo::process_stream_info_block(reader, &streaminfo_header, 8)?;
+
+    // смещение от начала файла, нужно для сообщений об ошибках
+    let mut offset = 8 + u64::from(streaminfo_header.length);
+
+    // STREAMINFO может быть единственным блоком метаданных
+    let blocks = if streaminfo_header.is_last {
+        Vec::new()
+    } else {
+        process_metadata(reader, &mut offset)?
+    };
+
+    Ok(Metadata {
+        stream_info,
+        blocks,
+        audio_offset: offset,
+    })
+}
+
+// сигнатура, STREAMINFO и блоки в порядке metadata.blocks, последний помечается is_last
+// audio_offset не используется: аудио после метаданных пишет вызывающий
+pub fn metadata_to_bytes(metadata: &Metadata) -> Result<Vec<u8>> {
+    let mut bytes = b"fLaC".to_vec();
+
+    push_block(
+        &mut bytes,
+        0,
+        &metadata.stream_info.to_bytes(),
+        metadata.blocks.is_empty(),
+    )?;
+    for (i, block) in metadata.blocks.iter().enumerate() {
+        push_block(
+            &mut bytes,
+            block.block_type(),
+            &block.to_bytes(),
+            i + 1 == metadata.blocks.len(),
+        )?;
+    }
+
+    Ok(bytes)
+}
+
+pub fn write_metadata<W: Write>(writer: &mut W, metadata: &Metadata) -> Result<()> {
+    let bytes = metadata_to_bytes(metadata)?;
+    writer.write_all(&bytes).map_err(|source| FlacError::Io {
+        source,
+        offset: Offset::from_bytes(0),
+    })
+}
+
+// заголовок и содержимое блока, ошибка если блок не влезает в 24 бита длины
+fn push_block(bytes: &mut Vec<u8>, block_type: u8, data: &[u8], is_last: bool) -> Result<()> {
+    let length = u32::try_from(data.len())
+        .ok()
+        .filter(|&length| length <= MAX_BLOCK_LENGTH)
+        .ok_or(FlacError::InvalidData {
+            reason: "Metadata block is too long",
+            offset: Offset::from_bytes(bytes.len() as u64),
+        })?;
+
+    let header = MetadataBlockHeader {
+        is_last,
+        block_type,
+        length,
+    };
+    bytes.extend_from_slice(&header.to_bytes());
+    bytes.extend_from_slice(data);
+    Ok(())
+}
+
+fn check_flac_header<R: Read>(reader: &mut R) -> Result<()> {
+    let mut format_part = [0u8; 4];
+    reader
+        .read_exact(&mut format_part)
+        .map_err(|source| FlacError::Io {
+            source,
+            offset: Offset::from_bytes(0),
+        })?;
+    if &format_part != b"fLaC" {
+        return Err(FlacError::InvalidSync {
+            offset: Offset::from_bytes(0),
+        });
+    }
+    Ok(())
+}
+
+// offset - смещение заголовка от начала файла
+pub fn get_header<R: Read>(reader: &mut R, offset: u64) -> Result<MetadataBlockHeader> {
     let mut header = [0u8; 4];
-    file.read_exact(&mut header)?;
+    reader
+        .read_exact(&mut header)
+        .map_err(|source| FlacError::Io {
+            source,
+            offset: Offset::from_bytes(offset),
+        })?;
 
     // побитовая операция
     // первый бит 0 или 1 если 0 то это не последний блок метаданных
@@ -14,13 +261,18 @@ pub fn get_header(file: &mut File) -> Result<(bool, u8, u32), std::io::Error> {
     // следующие 3 байта - длина блока метаданных
     // собираю 24 бита из 3 байт
     // сдвигаю первый байт на 16 бит влево, второй на 8 бит и добавляю третий
-    let length = ((header[1] as u32) << 16) | ((header[2] as u32) << 8) | (header[3] as u32);
+    let length = (u32::from(header[1]) << 16) | (u32::from(header[2]) << 8) | u32::from(header[3]);
 
-    Ok((is_last, block_type, length))
+    Ok(MetadataBlockHeader {
+        is_last,
+        block_type,
+        length,
+    })
 }
 
-pub fn process_metadata(file: &mut File) -> io::Result<()> {
-    // скип остальных блоков метаданных
+// чтение блоков метаданных после STREAMINFO до последнего
+// offset - смещение первого блока от начала файла, обновляется по мере чтения
+pub fn process_metadata<R: Read>(reader: &mut R, offset: &mut u64) -> Result<Vec<MetadataBlock>> {
     /*
     0	Streaminfo
     1	Padding
@@ -30,26 +282,351 @@ pub fn process_metadata(file: &mut File) -> io::Result<()> {
     5	Cuesheet
     6	Picture
     */
+    let mut blocks = Vec::new();
+
     loop {
-        let (is_last, block_type, length) = get_header(file)?;
-
-        // пока работает только обработка блока картинки
-        match block_type {
-            // блок картинки
-            6 => {
-                let mut buffer = vec![0u8; length as usize];
-                file.read_exact(&mut buffer)?;
-                picture::PictureBlock::process_picture_block(buffer);
+        let header = get_header(reader, *offset)?;
+        // содержимое блока идет сразу за 4 байтами заголовка
+        let block_offset = *offset + 4;
+
+        let block = match header.block_type {
+            // нули под будущие метаданные, читать их незачем
+            1 => {
+                skip_block(reader, header, block_offset)?;
+                MetadataBlock::Padding(header.length)
             }
-            _ => {
-                // пропускаем остальные блоки
-                file.seek(SeekFrom::Current(length as i64))?;
+            block_type => {
+                let buffer = read_block(reader, header, block_offset)?;
+                parse_block(block_type, buffer, block_offset)
             }
-        }
+        };
+
+        *offset = block_offset + u64::from(header.length);
 
-        if is_last {
+        blocks.push(block);
+
+        if header.is_last {
             break;
         }
     }
+
+    Ok(blocks)
+}
+
+// разбор содержимого блока по типу
+// offset здесь и в парсерах блоков - смещение содержимого блока от начала файла
+// из-за одного битого тега или картинки не должны теряться остальные метаданные,
+// поэтому блок с ошибкой остается байтами в MetadataBlock::Invalid вместе с ошибкой
+fn parse_block(block_type: u8, buffer: Vec<u8>, offset: u64) -> MetadataBlock {
+    let block = match block_type {
+        2 => {
+            Application::process_application_block(&buffer, offset).map(MetadataBlock::Application)
+        }
+        3 => SeekTable::process_seek_table_block(&buffer, offset).map(MetadataBlock::SeekTable),
+        4 => VorbisComment::process_vorbis_comment_block(&buffer, offset)
+            .map(MetadataBlock::VorbisComment),
+        5 => CueSheet::process_cue_sheet_block(&buffer, offset).map(MetadataBlock::CueSheet),
+        6 => PictureBlock::process_picture_block(&buffer, offset).map(MetadataBlock::Picture),
+        _ => {
+            return MetadataBlock::Unknown {
+                block_type,
+                data: buffer,
+            };
+        }
+    };
+
+    block.unwrap_or_else(|error| MetadataBlock::Invalid {
+        block_type,
+        data: buffer,
+        error: Arc::new(error),
+    })
+}
+
+// курсор по содержимому блока с проверкой границ, общий для парсеров блоков
+// выход за конец блока - TruncatedMetadata со смещением от начала файла
+pub(crate) struct BlockReader<'a> {
+    block: &'a [u8],
+    position: usize,
+    block_type: u8,
+    offset: u64,
+}
+
+impl<'a> BlockReader<'a> {
+    pub(crate) fn new(block: &'a [u8], block_type: u8, offset: u64) -> Self {
+        BlockReader {
+            block,
+            position: 0,
+            block_type,
+            offset,
+        }
+    }
+
+    // смещение текущей позиции от начала файла
+    pub(crate) fn offset(&self) -> Offset {
+        Offset::from_bytes(self.offset + self.position as u64)
+    }
+
+    // сколько байт осталось до конца блока
+    pub(crate) fn remaining(&self) -> usize {
+        self.block.len() - self.position
+    }
+
+    // следующие length байт, ошибка если блок короче
+    pub(crate) fn read_bytes(&mut self, length: u32) -> Result<&'a [u8]> {
+        let bytes = self
+            .block
+            .get(self.position..)
+            .and_then(|rest| rest.get(..length as usize))
+            .ok_or(FlacError::TruncatedMetadata {
+                block_type: self.block_type,
+                offset: self.offset(),
+            })?;
+        self.position += bytes.len();
+        Ok(bytes)
+    }
+
+    pub(crate) fn read_array<const N: usize>(&mut self) -> Result<[u8; N]> {
+        let mut bytes = [0u8; N];
+        #[allow(clippy::cast_possible_truncation)]
+        bytes.copy_from_slice(self.read_bytes(N as u32)?);
+        Ok(bytes)
+    }
+
+    pub(crate) fn read_u8(&mut self) -> Result<u8> {
+        Ok(self.read_array::<1>()?[0])
+    }
+
+    pub(crate) fn read_u32(&mut self) -> Result<u32> {
+        Ok(u32::from_be_bytes(self.read_array()?))
+    }
+
+    // VORBIS_COMMENT хранит длины в little-endian, как Ogg Vorbis
+    pub(crate) fn read_u32_le(&mut self) -> Result<u32> {
+        Ok(u32::from_le_bytes(self.read_array()?))
+    }
+
+    pub(crate) fn read_u64(&mut self) -> Result<u64> {
+        Ok(u64::from_be_bytes(self.read_array()?))
+    }
+
+    // UTF-8 строка длиной length байт, reason - текст ошибки для невалидной строки
+    pub(crate) fn read_string(&mut self, length: u32, reason: &'static str) -> Result<String> {
+        let offset = self.offset();
+        let bytes = self.read_bytes(length)?;
+        String::from_utf8(bytes.to_vec()).map_err(|_| FlacError::InvalidData { reason, offset })
+    }
+}
+
+// чтение содержимого блока целиком
+fn read_block<R: Read>(
+    reader: &mut R,
+    header: MetadataBlockHeader,
+    offset: u64,
+) -> Result<Vec<u8>> {
+    let mut buffer = vec![0u8; header.length as usize];
+    reader
+        .read_exact(&mut buffer)
+        .map_err(|source| FlacError::Io {
+            source,
+            offset: Offset::from_bytes(offset),
+        })?;
+    Ok(buffer)
+}
+
+// пропуск содержимого блока чтением, seek есть не у всех ридеров
+fn skip_block<R: Read>(reader: &mut R, header: MetadataBlockHeader, offset: u64) -> Result<()> {
+    let length = u64::from(header.length);
+    let skipped =
+        io::copy(&mut reader.by_ref().take(length), &mut io::sink()).map_err(|source| {
+            FlacError::Io {
+                source,
+                offset: Offset::from_bytes(offset),
+            }
+        })?;
+
+    if skipped != length {
+        return Err(FlacError::TruncatedMetadata {
+            block_type: header.block_type,
+            offset: Offset::from_bytes(offset),
+        });
+    }
+
     Ok(())
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cue_sheet::{CueIndex, CueTrack};
+    use crate::seek_table::SeekPoint;
+
+    fn stream_info() -> StreamInfo {
+        StreamInfo::new(4096, 4096, 0, 0, 44100, 2, 16, 0, [0; 16])
+    }
+
+    #[test]
+    fn every_block_type_round_trips() {
+        let metadata = Metadata {
+            stream_info: stream_info(),
+            blocks: vec![
+                MetadataBlock::Padding(10),
+                MetadataBlock::Application(Application {
+                    id: *b"test",
+                    data: vec![1, 2, 3],
+                }),
+                MetadataBlock::SeekTable(SeekTable {
+                    points: vec![
+                        SeekPoint {
+                            sample_number: 0,
+                            offset: 0,
+                            frame_samples: 4096,
+                        },
+                        SeekPoint {
+                            sample_number: u64::MAX,
+                            offset: 0,
+                            frame_samples: 0,
+                        },
+                    ],
+                }),
+                MetadataBlock::VorbisComment(VorbisComment {
+                    vendor: "test".to_string(),
+                    comments: vec![
+                        ("ARTIST".to_string(), "Кто-то".to_string()),
+                        ("TITLE".to_string(), "a=b".to_string()),
+                    ],
+                    unparsed: vec![(1, Vec::new())],
+                }),
+                MetadataBlock::CueSheet(CueSheet {
+                    media_catalog_number: "1234567890123".to_string(),
+                    lead_in_samples: 88200,
+                    is_cd: true,
+                    tracks: vec![
+                        CueTrack {
+                            offset: 0,
+                            number: 1,
+                            isrc: "ABCDE1234567".to_string(),
+                            is_audio: true,
+                            pre_emphasis: false,
+                            indices: vec![CueIndex {
+                                offset: 0,
+                                number: 1,
+                            }],
+                        },
+                        CueTrack {
+                            offset: 441_000,
+                            number: 170,
+                            isrc: String::new(),
+                            is_audio: true,
+                            pre_emphasis: false,
+                            indices: Vec::new(),
+                        },
+                    ],
+                }),
+                MetadataBlock::Picture(PictureBlock {
+                    picture_type: 3,
+                    media_type: "image/png".to_string(),
+                    description: "Обложка".to_string(),
+                    width: 1,
+                    height: 1,
+                    color_depth: 24,
+                    colors_used: 0,
+                    data: vec![0x89, b'P', b'N', b'G'],
+                }),
+                MetadataBlock::Unknown {
+                    block_type: 9,
+                    data: vec![9; 5],
+                },
+            ],
+            audio_offset: 0,
+        };
+
+        let bytes = metadata_to_bytes(&metadata).unwrap();
+        let parsed = read_metadata(&mut bytes.as_slice()).unwrap();
+
+        assert_eq!(parsed.audio_offset, bytes.len() as u64);
+        assert_eq!(parsed.blocks.len(), metadata.blocks.len());
+        for (parsed, block) in parsed.blocks.iter().zip(&metadata.blocks) {
+            assert_eq!(parsed.block_type(), block.block_type());
+            assert_eq!(parsed.to_bytes(), block.to_bytes());
+        }
+        assert!(parsed.applications().eq(metadata.applications()));
+        assert_eq!(parsed.tags(), metadata.tags());
+        assert_eq!(parsed.cue_sheet(), metadata.cue_sheet());
+        assert!(parsed.pictures().eq(metadata.pictures()));
+        assert_eq!(metadata_to_bytes(&parsed).unwrap(), bytes);
+    }
+
+    // содержимое VORBIS_COMMENT с вендором test и полями как есть
+    fn vorbis_comment_bytes(count: u32, fields: &[&[u8]]) -> Vec<u8> {
+        let mut tags = Vec::new();
+        tags.extend_from_slice(&4u32.to_le_bytes());
+        tags.extend_from_slice(b"test");
+        tags.extend_from_slice(&count.to_le_bytes());
+        for field in fields {
+            tags.extend_from_slice(&u32::try_from(field.len()).unwrap().to_le_bytes());
+            tags.extend_from_slice(field);
+        }
+        tags
+    }
+
+    #[test]
+    fn unparseable_block_is_kept_with_error() {
+        // VORBIS_COMMENT обещает два поля, а содержит одно
+        let tags = vorbis_comment_bytes(2, &[b"TITLE=a"]);
+
+        let metadata = Metadata {
+            stream_info: stream_info(),
+            blocks: vec![
+                MetadataBlock::Unknown {
+                    block_type: 4,
+                    data: tags.clone(),
+                },
+                MetadataBlock::Unknown {
+                    block_type: 100,
+                    data: vec![1, 2],
+                },
+            ],
+            audio_offset: 0,
+        };
+
+        let bytes = metadata_to_bytes(&metadata).unwrap();
+        let parsed = read_metadata(&mut bytes.as_slice()).unwrap();
+
+        assert!(parsed.tags().is_none());
+        assert!(matches!(
+            &parsed.blocks[..],
+            [
+                MetadataBlock::Invalid { block_type: 4, data, error },
+                MetadataBlock::Unknown { block_type: 100, .. },
+            ] if *data == tags
+                && matches!(**error, FlacError::TruncatedMetadata { block_type: 4, .. })
+        ));
+        assert_eq!(metadata_to_bytes(&parsed).unwrap(), bytes);
+    }
+
+    #[test]
+    fn malformed_tag_fields_are_kept_raw() {
+        let fields: [&[u8]; 6] = [
+            b"TITLE=a",
+            b"",
+            b"BROKEN",
+            b"\xFF=x",
+            b"=empty name",
+            b"ARTIST=b",
+        ];
+        let tags = vorbis_comment_bytes(6, &fields);
+
+        let mut parsed = VorbisComment::process_vorbis_comment_block(&tags, 0).unwrap();
+        assert!(parsed.iter().eq([("TITLE", "a"), ("ARTIST", "b")]));
+        assert_eq!(parsed.unparsed.len(), 4);
+        assert_eq!(parsed.to_bytes(), tags);
+
+        // сырые записи остаются на месте при правке и уходят в конец без своего поля
+        parsed.set("TITLE", "c").unwrap();
+        parsed.remove("ARTIST");
+        assert_eq!(
+            parsed.to_bytes(),
+            vorbis_comment_bytes(5, &[b"TITLE=c", b"", b"BROKEN", b"\xFF=x", b"=empty name"])
+        );
+    }
+}
@@ -0,0 +1,199 @@
+use std::io::{self, Read};
+
+use crate::decoder::Decoder;
+use crate::error::{FlacError, Offset, Result};
+
+// Read поверх декодера: отдает PCM little-endian вперемешку по каналам,
+// каждый сэмпл занимает ceil(bps / 8) байт
+// сэмплы всегда со знаком, в том числе 8-битные, которые в WAV беззнаковые
+pub struct PcmReader<R> {
+    decoder: Decoder<R>,
+    // разрядность выходных сэмплов
+    bps: u32,
+    // байты текущего фрейма и сколько из них уже отдано
+    buffer: Vec<u8>,
+    position: usize,
+}
+
+impl<R: Read> PcmReader<R> {
+    // разрядность как в потоке
+    #[must_use]
+    pub fn new(decoder: Decoder<R>) -> Self {
+        let bps = u32::from(decoder.stream_info().bps);
+        Self::build(decoder, bps)
+    }
+
+    // сэмплы приводятся к bps бит: сдвигом влево если поток мельче,
+    // отбрасыванием младших битов если глубже
+    // bps от 1 до 32, как в FLAC
+    pub fn with_bit_depth(decoder: Decoder<R>, bps: u32) -> Result<Self> {
+        if !(1..=32).contains(&bps) {
+            return Err(FlacError::Unsupported {
+                feature: "PCM bit depth",
+                offset: Offset::from_bytes(0),
+            });
+        }
+        Ok(Self::build(decoder, bps))
+    }
+
+    fn build(decoder: Decoder<R>, bps: u32) -> Self {
+        let stream_info = decoder.stream_info();
+        let capacity = usize::from(stream_info.max_block_size)
+            * usize::from(stream_info.channels)
+            * bps.div_ceil(8) as usize;
+
+        PcmReader {
+            decoder,
+            bps,
+            buffer: Vec::with_capacity(capacity),
+            position: 0,
+        }
+    }
+
+    #[must_use]
+    pub fn decoder(&self) -> &Decoder<R> {
+        &self.decoder
+    }
+
+    #[must_use]
+    pub fn into_inner(self) -> Decoder<R> {
+        self.decoder
+    }
+
+    // декодирует следующий фрейм в буфер, false в конце потока
+    fn fill_buffer(&mut self) -> io::Result<bool> {
+        let Some(info) = self.decoder.decode_frame()? else {
+            return Ok(false);
+        };
+
+        let stream_bps = u32::from(self.decoder.stream_info().bps);
+        let bytes_per_sample = self.bps.div_ceil(8) as usize;
+        let channels = usize::from(info.header.channel_assignment.channels());
+
+        self.buffer.clear();
+        self.position = 0;
+
+        for i in 0..self.decoder.samples_per_channel() {
+            for channel in 0..channels {
+                let sample = self.decoder.channel(channel)[i];
+                let sample = if self.bps >= stream_bps {
+                    sample << (self.bps - stream_bps)
+                } else {
+                    sample >> (stream_bps - self.bps)
+                };
+
+                self.buffer
+                    .extend_from_slice(&sample.to_le_bytes()[..bytes_per_sample]);
+            }
+        }
+
+        Ok(true)
+    }
+}
+
+impl<R: Read> Read for PcmReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        if buf.is_empty() {
+            return Ok(0);
+        }
+
+        // буфер кончился - декодирую следующий фрейм, 0 байт значит конец потока
+        while self.position == self.buffer.len() {
+            if !self.fill_buffer()? {
+                return Ok(0);
+            }
+        }
+
+        let available = &self.buffer[self.position..];
+        let count = available.len().min(buf.len());
+        buf[..count].copy_from_slice(&available[..count]);
+        self.position += count;
+
+        Ok(count)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Cursor;
+
+    use super::*;
+    use crate::crc;
+    use crate::metedata_blocks::{self, Metadata};
+    use crate::stream_info::StreamInfo;
+
+    const LEFT: [i8; 4] = [-128, -1, 0, 127];
+    const RIGHT: [i8; 4] = [1, 2, 3, 4];
+
+    // стерео, 8 бит, 44100 Гц: один фрейм с VERBATIM сабфреймами LEFT и RIGHT
+    fn decoder() -> Decoder<Cursor<Vec<u8>>> {
+        let stream_info = StreamInfo::new(16, 16, 0, 0, 44100, 2, 8, 4, [0; 16]);
+        let mut bytes = metedata_blocks::metadata_to_bytes(&Metadata {
+            stream_info,
+            blocks: Vec::new(),
+            audio_offset: 0,
+        })
+        .unwrap();
+
+        let start = bytes.len();
+        // блок 8 битами в конце заголовка и 44100 Гц, два независимых канала и 8 бит,
+        // фрейм 0, размер блока - 1
+        bytes.extend_from_slice(&[0xFF, 0xF8, 0x69, 0x12, 0x00, 3]);
+        bytes.push(crc::crc8(&bytes[start..]));
+        for channel in [LEFT, RIGHT] {
+            bytes.push(0b0000_0010);
+            bytes.extend(channel.map(|sample| sample.to_be_bytes()[0]));
+        }
+        let crc16 = crc::crc16(&bytes[start..]);
+        bytes.extend_from_slice(&crc16.to_be_bytes());
+
+        Decoder::new(Cursor::new(bytes)).unwrap()
+    }
+
+    // весь PCM, читается кусками по 3 байта, чтобы границы чтения не совпадали с сэмплами
+    fn read_all(mut reader: PcmReader<Cursor<Vec<u8>>>) -> Vec<u8> {
+        let mut pcm = Vec::new();
+        let mut buf = [0; 3];
+        loop {
+            let count = reader.read(&mut buf).unwrap();
+            if count == 0 {
+                return pcm;
+            }
+            pcm.extend_from_slice(&buf[..count]);
+        }
+    }
+
+    // ожидаемый PCM: каналы вперемешку, сэмпл после convert в bytes младших байтах
+    fn interleaved(convert: impl Fn(i8) -> i32, bytes: usize) -> Vec<u8> {
+        LEFT.into_iter()
+            .zip(RIGHT)
+            .flat_map(|(left, right)| [left, right])
+            .flat_map(|sample| convert(sample).to_le_bytes()[..bytes].to_vec())
+            .collect()
+    }
+
+    #[test]
+    fn pcm_is_signed_interleaved_little_endian() {
+        let pcm = read_all(PcmReader::new(decoder()));
+        assert_eq!(pcm, [0x80, 0x01, 0xFF, 0x02, 0x00, 0x03, 0x7F, 0x04]);
+
+        let pcm = read_all(PcmReader::with_bit_depth(decoder(), 16).unwrap());
+        assert_eq!(pcm, interleaved(|sample| i32::from(sample) << 8, 2));
+
+        let pcm = read_all(PcmReader::with_bit_depth(decoder(), 24).unwrap());
+        assert_eq!(pcm, interleaved(|sample| i32::from(sample) << 16, 3));
+
+        let pcm = read_all(PcmReader::with_bit_depth(decoder(), 4).unwrap());
+        assert_eq!(pcm, interleaved(|sample| i32::from(sample) >> 4, 1));
+    }
+
+    #[test]
+    fn unsupported_bit_depth_is_an_error() {
+        for bps in [0, 33] {
+            assert!(matches!(
+                PcmReader::with_bit_depth(decoder(), bps),
+                Err(FlacError::Unsupported { .. })
+            ));
+        }
+    }
+}
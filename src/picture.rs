@@ -1,96 +1,87 @@
-use std::io::Cursor;
+use crate::error::Result;
+use crate::metedata_blocks::BlockReader;
 
-use image::ImageReader;
-
-#[derive(Debug)]
+#[derive(Debug, Clone, PartialEq, Eq)]
 pub struct PictureBlock {
+    // тип картинки по ID3v2 APIC: 3 - обложка, 4 - задняя сторона и т.д.
     pub picture_type: u32,
     pub media_type: String,
-    pub description_length: u32,
+    pub description: String,
+    // 0 если не указаны
     pub width: u32,
     pub height: u32,
     pub color_depth: u32,
+    // количество цветов для палитровых картинок, 0 для остальных
     pub colors_used: u32,
-    pub picture_data_length: u32,
+    // содержимое файла картинки как есть
+    pub data: Vec<u8>,
 }
 
 impl PictureBlock {
-    // получение и сохранение картинки из метаданных
-    pub fn process_picture_block(picture_block: Vec<u8>) {
-        let mut step = 0;
-
-        let picture_type = u32::from_be_bytes(picture_block[step..step + 4].try_into().unwrap());
-        step += 4;
+    pub fn process_picture_block(picture_block: &[u8], offset: u64) -> Result<Self> {
+        let mut reader = BlockReader::new(picture_block, 6, offset);
 
-        let media_type_length =
-            u32::from_be_bytes(picture_block[step..step + 4].try_into().unwrap());
-        step += 4;
+        let picture_type = reader.read_u32()?;
 
+        let media_type_length = reader.read_u32()?;
         let media_type =
-            std::str::from_utf8(&picture_block[step..step + media_type_length as usize]).unwrap();
-        step += media_type_length as usize;
-
-        let description_length =
-            u32::from_be_bytes(picture_block[step..step + 4].try_into().unwrap());
-        step += 4;
-        step += description_length as usize;
-
-        let mut width = u32::from_be_bytes(picture_block[step..step + 4].try_into().unwrap());
-        step += 4;
-        let mut height = u32::from_be_bytes(picture_block[step..step + 4].try_into().unwrap());
-        step += 4;
-        let color_depth = u32::from_be_bytes(picture_block[step..step + 4].try_into().unwrap());
-        step += 4;
-        let colors_used = u32::from_be_bytes(picture_block[step..step + 4].try_into().unwrap());
-        step += 4;
-        let picture_data_length =
-            u32::from_be_bytes(picture_block[step..step + 4].try_into().unwrap());
-        step += 4;
-        let picture_data = &picture_block[step..step + picture_data_length as usize];
+            reader.read_string(media_type_length, "Picture media type is not valid UTF-8")?;
 
-        // сохранение картинки в файл
-        let file_name = format!(
-            "picture_{}.{}",
-            picture_type,
-            match media_type {
-                "image/jpeg" => "jpg",
-                "image/png" => "png",
-                _ => "bin",
-            }
-        );
+        let description_length = reader.read_u32()?;
+        let description =
+            reader.read_string(description_length, "Picture description is not valid UTF-8")?;
 
-        let cursor = Cursor::new(picture_data);
-
-        match ImageReader::new(cursor).with_guessed_format() {
-            Ok(reader) => match reader.decode() {
-                Ok(image) => {
-                    if width == 0 || height == 0 {
-                        width = image.width();
-                        height = image.height();
-                    }
-                    match image.save(&file_name) {
-                        Ok(_) => println!("Saved picture to {}", file_name),
-                        Err(e) => println!("Failed to save picture: {}", e),
-                    }
-                }
-                Err(e) => println!("Failed to decode image: {}", e),
-            },
-            Err(e) => {
-                println!("Failed to read image dimensions: {}", e);
-            }
-        }
+        let width = reader.read_u32()?;
+        let height = reader.read_u32()?;
+        let color_depth = reader.read_u32()?;
+        let colors_used = reader.read_u32()?;
+        let picture_data_length = reader.read_u32()?;
+        let data = reader.read_bytes(picture_data_length)?.to_vec();
 
-        let picture = PictureBlock {
+        Ok(PictureBlock {
             picture_type,
-            media_type: media_type.to_string(),
-            description_length,
+            media_type,
+            description,
             width,
             height,
             color_depth,
             colors_used,
-            picture_data_length,
-        };
+            data,
+        })
+    }
 
-        println!("{:#?}", picture);
+    // содержимое блока PICTURE
+    #[must_use]
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut bytes = Vec::with_capacity(
+            32 + self.media_type.len() + self.description.len() + self.data.len(),
+        );
+        bytes.extend_from_slice(&self.picture_type.to_be_bytes());
+        write_bytes(&mut bytes, self.media_type.as_bytes());
+        write_bytes(&mut bytes, self.description.as_bytes());
+        bytes.extend_from_slice(&self.width.to_be_bytes());
+        bytes.extend_from_slice(&self.height.to_be_bytes());
+        bytes.extend_from_slice(&self.color_depth.to_be_bytes());
+        bytes.extend_from_slice(&self.colors_used.to_be_bytes());
+        write_bytes(&mut bytes, &self.data);
+        bytes
     }
+
+    // расширение файла по media type
+    #[must_use]
+    pub fn extension(&self) -> &'static str {
+        match self.media_type.as_str() {
+            "image/jpeg" => "jpg",
+            "image/png" => "png",
+            "image/gif" => "gif",
+            _ => "bin",
+        }
+    }
+}
+
+// длина 32 бита и сами байты, длину блока проверяет запись метаданных
+fn write_bytes(bytes: &mut Vec<u8>, data: &[u8]) {
+    #[allow(clippy::cast_possible_truncation)]
+    bytes.extend_from_slice(&(data.len() as u32).to_be_bytes());
+    bytes.extend_from_slice(data);
 }
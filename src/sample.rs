@@ -0,0 +1,61 @@
+// тип сэмплов на выходе декодера
+// i16 всегда в полном диапазоне: младшие биты отбрасываются или дополняются нулями,
+// i32 отдается как есть; float нормализуются в [-1, 1] по разрядности потока
+pub trait Sample: Copy + Default {
+    // перевод декодированного сэмпла разрядности bps
+    fn from_sample(sample: i64, bps: u32) -> Self;
+}
+
+// 8 битное аудио растягивается до 16 бит сдвигом влево, 24 битное сжимается вправо
+impl Sample for i16 {
+    #[allow(clippy::cast_possible_truncation)]
+    fn from_sample(sample: i64, bps: u32) -> Self {
+        if bps > 16 {
+            (sample >> (bps - 16)) as i16
+        } else {
+            (sample << (16 - bps)) as i16
+        }
+    }
+}
+
+// 24 битное аудио лежит в младших битах i32 без сдвига
+impl Sample for i32 {
+    #[allow(clippy::cast_possible_truncation)]
+    fn from_sample(sample: i64, _bps: u32) -> Self {
+        sample as i32
+    }
+}
+
+impl Sample for f32 {
+    #[allow(clippy::cast_possible_truncation)]
+    fn from_sample(sample: i64, bps: u32) -> Self {
+        f64::from_sample(sample, bps) as f32
+    }
+}
+
+impl Sample for f64 {
+    #[allow(clippy::cast_precision_loss)]
+    fn from_sample(sample: i64, bps: u32) -> Self {
+        // делю на 2^(bps-1), самый отрицательный сэмпл дает ровно -1
+        sample as f64 / (1u64 << (bps - 1)) as f64
+    }
+}
+
+// раскладка каналов вперемешку: L R L R ...
+// out должен вмещать все сэмплы всех каналов
+pub(crate) fn interleave<S: Sample, C: AsRef<[i64]>>(channels: &[C], bps: u32, out: &mut [S]) {
+    let channel_count = channels.len();
+
+    for (channel, samples) in channels.iter().enumerate() {
+        for (i, &sample) in samples.as_ref().iter().enumerate() {
+            out[i * channel_count + channel] = S::from_sample(sample, bps);
+        }
+    }
+}
+
+// перевод одного канала в выходной тип
+pub(crate) fn convert<S: Sample>(samples: &[i64], bps: u32, out: &mut [S]) {
+    for (out, &sample) in out.iter_mut().zip(samples) {
+        *out = S::from_sample(sample, bps);
+    }
+}
@@ -0,0 +1,81 @@
+use crate::error::{FlacError, Offset, Result};
+
+// размер одной точки в блоке SEEKTABLE
+const SEEK_POINT_LENGTH: usize = 18;
+
+// точка перемотки
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SeekPoint {
+    // номер первого сэмпла фрейма
+    pub sample_number: u64,
+    // смещение фрейма от первого байта первого фрейма
+    pub offset: u64,
+    // количество сэмплов во фрейме
+    pub frame_samples: u16,
+}
+
+impl SeekPoint {
+    // заглушка, оставленная энкодером под будущую точку
+    #[must_use]
+    pub fn is_placeholder(&self) -> bool {
+        self.sample_number == u64::MAX
+    }
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct SeekTable {
+    pub points: Vec<SeekPoint>,
+}
+
+impl SeekTable {
+    pub fn process_seek_table_block(seek_table_block: &[u8], offset: u64) -> Result<Self> {
+        // блок состоит только из точек по 18 байт
+        if !seek_table_block.len().is_multiple_of(SEEK_POINT_LENGTH) {
+            return Err(FlacError::TruncatedMetadata {
+                block_type: 3,
+                offset: Offset::from_bytes(offset),
+            });
+        }
+
+        let points = seek_table_block
+            .chunks_exact(SEEK_POINT_LENGTH)
+            .map(|point| {
+                // 8 байт номер сэмпла, 8 байт смещение, 2 байта размер фрейма
+                let mut sample_number = [0u8; 8];
+                sample_number.copy_from_slice(&point[0..8]);
+                let mut point_offset = [0u8; 8];
+                point_offset.copy_from_slice(&point[8..16]);
+
+                SeekPoint {
+                    sample_number: u64::from_be_bytes(sample_number),
+                    offset: u64::from_be_bytes(point_offset),
+                    frame_samples: u16::from_be_bytes([point[16], point[17]]),
+                }
+            })
+            .collect();
+
+        Ok(SeekTable { points })
+    }
+
+    // содержимое блока SEEKTABLE
+    #[must_use]
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut bytes = Vec::with_capacity(self.points.len() * SEEK_POINT_LENGTH);
+        for point in &self.points {
+            bytes.extend_from_slice(&point.sample_number.to_be_bytes());
+            bytes.extend_from_slice(&point.offset.to_be_bytes());
+            bytes.extend_from_slice(&point.frame_samples.to_be_bytes());
+        }
+        bytes
+    }
+
+    // ближайшая точка не дальше sample, заглушки пропускаются
+    #[must_use]
+    pub fn nearest(&self, sample: u64) -> Option<&SeekPoint> {
+        // точки отсортированы по возрастанию номера сэмпла, заглушки в конце
+        self.points
+            .iter()
+            .filter(|point| !point.is_placeholder() && point.sample_number <= sample)
+            .max_by_key(|point| point.sample_number)
+    }
+}
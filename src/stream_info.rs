@@ -1,12 +1,18 @@
-use std::{fs::File, io::Read};
+use std::io::Read;
 
-#[derive(Debug)]
+use crate::error::{FlacError, Offset, Result};
+use crate::metedata_blocks::MetadataBlockHeader;
+
+// размер STREAMINFO в байтах
+const STREAMINFO_LENGTH: usize = 34;
+
+#[derive(Debug, Clone)]
 pub struct StreamInfo {
     pub min_block_size: u16,
     pub max_block_size: u16,
     pub min_frame_size: u32,
     pub max_frame_size: u32,
-    pub sample_rate: u64,
+    pub sample_rate: u32,
     pub channels: u8,
     pub bps: u8,
     pub total_samples: u64,
@@ -14,12 +20,14 @@ pub struct StreamInfo {
 }
 
 impl StreamInfo {
+    #[allow(clippy::too_many_arguments)]
+    #[must_use]
     pub fn new(
         min_block_size: u16,
         max_block_size: u16,
         min_frame_size: u32,
         max_frame_size: u32,
-        sample_rate: u64,
+        sample_rate: u32,
         channels: u8,
         bps: u8,
         total_samples: u64,
@@ -38,71 +46,98 @@ impl StreamInfo {
         }
     }
 
-    pub fn process_stream_info_block(file: &mut File) {
-            let streaminfo_header = get_header(file).expect("Error get_header!");
-
+    // заголовок блока уже прочитан, ридер стоит на его содержимом
+    pub fn process_stream_info_block<R: Read>(
+        reader: &mut R,
+        streaminfo_header: &MetadataBlockHeader,
+        offset: u64,
+    ) -> Result<Self> {
         // первый всегда идет STREAMINFO
-        // поменять потом с индексов на именованные поля
-        if streaminfo_header.1 != 0 {
-            panic!("Expect STREAMINFO (type 0)");
+        if streaminfo_header.block_type != 0 {
+            return Err(FlacError::InvalidData {
+                reason: "First metadata block is not STREAMINFO",
+                offset: Offset::from_bytes(offset - 4),
+            });
+        }
+
+        if (streaminfo_header.length as usize) < STREAMINFO_LENGTH {
+            return Err(FlacError::TruncatedMetadata {
+                block_type: 0,
+                offset: Offset::from_bytes(offset),
+            });
         }
 
         // создаю вектор в длину блока и читаю его содержимое
-        let mut streaminfo = vec![0u8; streaminfo_header.2 as usize];
-        file.read_exact(&mut streaminfo).unwrap();
+        let mut streaminfo = vec![0u8; streaminfo_header.length as usize];
+        reader
+            .read_exact(&mut streaminfo)
+            .map_err(|source| FlacError::Io {
+                source,
+                offset: Offset::from_bytes(offset),
+            })?;
 
         // чтение информация из STREAMINFO
         // собираю значения из байт массива согласно докам
         // TODO: переписать на from_be_bytes где возможно
-        let min_block_size = u16::from_be_bytes(streaminfo[0..2].try_into().unwrap());
-        let max_block_size = u16::from_be_bytes(streaminfo[2..4].try_into().unwrap());
+        let min_block_size = u16::from_be_bytes(be_bytes(&streaminfo, 0));
+        let max_block_size = u16::from_be_bytes(be_bytes(&streaminfo, 2));
         let min_frame_size = u32::from_be_bytes([0, streaminfo[4], streaminfo[5], streaminfo[6]]);
         let max_frame_size = u32::from_be_bytes([0, streaminfo[7], streaminfo[8], streaminfo[9]]);
         // беру сразу 8 байт с 10 по 17 и комбинирую в одно 64 битное число
         // так как дальше идут значения которые занимают биты в этих байтах
         // так удобнее всего двигаться внутри байтов
-        let combinated = u64::from_be_bytes(streaminfo[10..18].try_into().unwrap());
+        let combinated = u64::from_be_bytes(be_bytes(&streaminfo, 10));
         // получение 16 байт контрольной суммы MD5
-        let checksum_combined: [u8; 16] = streaminfo[18..34].try_into().unwrap();
+        let checksum_combined: [u8; 16] = be_bytes(&streaminfo, 18);
         // так как значение занимает 20 то сдвигаю на 12 бита вправо от 32 и маской беру 20 бит
-        let sample_rate = (combinated >> 44) & 0xFFFFF; // 20 bit
+        let sample_rate = ((combinated >> 44) & 0xFFFFF) as u32; // 20 bit
         // сдвигаю от 32 на 9 бит и маской беру 3 бита
-        let channels = (combinated >> 41) & 0x7; // 3 bit
+        // в файле хранится количество каналов минус 1
+        let channels = ((combinated >> 41) & 0x7) as u8 + 1; // 3 bit
         // сдвигаю от 32 на 4 бит и маской беру 5 бит
-        let bps = (combinated >> 36) & 0x1F; // 5 bit
+        // в файле хранится bits per sample минус 1
+        let bps = ((combinated >> 36) & 0x1F) as u8 + 1; // 5 bit
         // все что осталось забираю маской
-        let total_samples = combinated & 0xFFFFFFFFF; // 36 bit
+        let total_samples = combinated & 0xF_FFFF_FFFF; // 36 bit
 
-        let steam_info = StreamInfo::new(
+        Ok(StreamInfo::new(
             min_block_size,
             max_block_size,
             min_frame_size,
             max_frame_size,
             sample_rate,
-            channels as u8,
-            bps as u8,
+            channels,
+            bps,
             total_samples,
             checksum_combined,
-        );
-
-        println!("{:#?}", steam_info);
+        ))
     }
+}
 
-    fn get_header(file: &mut File) -> Result<(bool, u8, u32), std::io::Error> {
-        let mut header = [0u8; 4];
-        file.read_exact(&mut header)?;
-
-        // побитовая операция
-        // первый бит 0 или 1 если 0 то это не последний блок метаданных
-        // следующие 7 бит - тип блока 0 - STREAMINFO 1 - PADDING и тд
-        let is_last = (header[0] & 0x80) != 0;
-        let block_type = header[0] & 0x7F;
-
-        // следующие 3 байта - длина блока метаданных
-        // собираю 24 бита из 3 байт
-        // сдвигаю первый байт на 16 бит влево, второй на 8 бит и добавляю третий
-        let length = ((header[1] as u32) << 16) | ((header[2] as u32) << 8) | (header[3] as u32);
-
-        Ok((is_last, block_type, length))
+impl StreamInfo {
+    // содержимое блока STREAMINFO в том же виде, как его читает process_stream_info_block
+    #[must_use]
+    pub fn to_bytes(&self) -> [u8; STREAMINFO_LENGTH] {
+        let mut bytes = [0u8; STREAMINFO_LENGTH];
+        bytes[0..2].copy_from_slice(&self.min_block_size.to_be_bytes());
+        bytes[2..4].copy_from_slice(&self.max_block_size.to_be_bytes());
+        // размеры фреймов занимают по 24 бита
+        bytes[4..7].copy_from_slice(&self.min_frame_size.to_be_bytes()[1..]);
+        bytes[7..10].copy_from_slice(&self.max_frame_size.to_be_bytes()[1..]);
+        // 20 бит частоты, 3 бита каналов - 1, 5 бит разрядности - 1, 36 бит длины
+        let combinated = (u64::from(self.sample_rate) & 0xFFFFF) << 44
+            | (u64::from(self.channels - 1) & 0x7) << 41
+            | (u64::from(self.bps - 1) & 0x1F) << 36
+            | self.total_samples & 0xF_FFFF_FFFF;
+        bytes[10..18].copy_from_slice(&combinated.to_be_bytes());
+        bytes[18..34].copy_from_slice(&self.checksum_combined);
+        bytes
     }
-}
\ No newline at end of file
+}
+
+// N байт начиная со start, длина блока проверена заранее
+fn be_bytes<const N: usize>(data: &[u8], start: usize) -> [u8; N] {
+    let mut bytes = [0u8; N];
+    bytes.copy_from_slice(&data[start..start + N]);
+    bytes
+}
@@ -0,0 +1,509 @@
+use std::io::{Read, Seek};
+
+use bitstream_io::{BigEndian, BitRead, BitReader};
+
+use crate::error::{self, FlacError, Result};
+
+// тип сабфрейма вместе с порядком предсказания
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SubframeType {
+    Constant,
+    Verbatim,
+    Fixed(u8),
+    Lpc(u8),
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct SubframeHeader {
+    pub subframe_type: SubframeType,
+    pub wasted_bits: u32,
+}
+
+pub struct Subframe {
+    pub subframe_header: SubframeHeader,
+    // сэмплы канала, после декодирования фрейма уже без межканальной декорреляции
+    pub samples: Vec<i64>,
+}
+
+// функция для поиска количества битов, отведенных под убитые биты
+fn find_wasted_bits<R: Read + Seek>(reader: &mut BitReader<R, BigEndian>) -> Result<u32> {
+    let wasted_bits_flag = reader.read::<1, u8>().map_err(|e| error::io(e, reader))?;
+    let mut k = 0;
+    if wasted_bits_flag == 1 {
+        while reader.read::<1, u8>().map_err(|e| error::io(e, reader))? == 0 {
+            k += 1;
+        }
+        k += 1;
+    }
+
+    Ok(k)
+}
+
+// чтение заголовка сабфрейма: 1 бит паддинга, 6 бит типа и флаг убитых битов
+fn read_subframe_header<R: Read + Seek>(
+    reader: &mut BitReader<R, BigEndian>,
+) -> Result<SubframeHeader> {
+    // 1 бит - должен быть 0
+    if reader.read::<1, u8>().map_err(|e| error::io(e, reader))? != 0 {
+        return Err(FlacError::InvalidData {
+            reason: "Invalid subframe padding bit",
+            offset: error::position(reader),
+        });
+    }
+
+    // 6 бит - тип сабфрейма
+    let subframe_type_bits = reader.read::<6, u8>().map_err(|e| error::io(e, reader))?;
+
+    // получение типа и порядка
+    let subframe_type = match subframe_type_bits {
+        0b00_0000 => SubframeType::Constant,
+        0b00_0001 => SubframeType::Verbatim,
+        // порядок fixed от 0 до 4
+        0b00_1000..=0b00_1100 => SubframeType::Fixed(subframe_type_bits - 0b00_1000),
+        // порядок lpc от 1 до 32
+        0b10_0000..=0b11_1111 => SubframeType::Lpc(subframe_type_bits - 0b01_1111),
+        _ => {
+            return Err(FlacError::Reserved {
+                field: "subframe type",
+                offset: error::position(reader),
+            });
+        }
+    };
+
+    let wasted_bits = find_wasted_bits(reader)?;
+
+    Ok(SubframeHeader {
+        subframe_type,
+        wasted_bits,
+    })
+}
+
+// декодирование одного сабфрейма в буфер samples длиной в размер блока
+// память не выделяется, остаток пишется прямо в samples и поверх него
+// накладывается предсказание
+pub(crate) fn decode_subframe_into<R: Read + Seek>(
+    reader: &mut BitReader<R, BigEndian>,
+    bps: u32,
+    samples: &mut [i64],
+) -> Result<SubframeHeader> {
+    // сэмплы хранятся в i64, этого хватает и для 32 битного аудио,
+    // и для side канала, которому нужен 33-й бит
+    if !(1..=33).contains(&bps) {
+        return Err(FlacError::Unsupported {
+            feature: "subframe bit depth",
+            offset: error::position(reader),
+        });
+    }
+
+    let subframe_header = read_subframe_header(reader)?;
+
+    // убитые биты не хранятся в потоке, поэтому все значения читаются
+    // с уменьшенной разрядностью
+    let wasted_bits = subframe_header.wasted_bits;
+    if wasted_bits >= bps {
+        return Err(FlacError::InvalidData {
+            reason: "Wasted bits exceed subframe bit depth",
+            offset: error::position(reader),
+        });
+    }
+    let bps = bps - wasted_bits;
+
+    // вызов конкретных функций декодирования в зависимости от типа сабфрейма
+    match subframe_header.subframe_type {
+        SubframeType::Constant => constant_value(reader, bps, samples)?,
+        SubframeType::Verbatim => verbatim(reader, bps, samples)?,
+        SubframeType::Fixed(order) => fixed_prediction(reader, order, bps, samples)?,
+        SubframeType::Lpc(order) => lpc(reader, order, bps, samples)?,
+    }
+
+    // возвращаю убитые биты на место
+    if wasted_bits > 0 {
+        for sample in samples.iter_mut() {
+            *sample <<= wasted_bits;
+        }
+    }
+
+    Ok(subframe_header)
+}
+
+// весь блок состоит из одного значения
+fn constant_value<R: Read + Seek>(
+    reader: &mut BitReader<R, BigEndian>,
+    bps: u32,
+    samples: &mut [i64],
+) -> Result<()> {
+    let value = reader
+        .read_signed_var::<i64>(bps)
+        .map_err(|e| error::io(e, reader))?;
+
+    samples.fill(value);
+
+    Ok(())
+}
+
+// сэмплы хранятся как есть, без сжатия
+fn verbatim<R: Read + Seek>(
+    reader: &mut BitReader<R, BigEndian>,
+    bps: u32,
+    samples: &mut [i64],
+) -> Result<()> {
+    for sample in samples.iter_mut() {
+        *sample = reader
+            .read_signed_var::<i64>(bps)
+            .map_err(|e| error::io(e, reader))?;
+    }
+
+    Ok(())
+}
+
+fn fixed_prediction<R: Read + Seek>(
+    reader: &mut BitReader<R, BigEndian>,
+    order: u8,
+    bps: u32,
+    samples: &mut [i64],
+) -> Result<()> {
+    // в длину порядка читаю прогревочные семплы
+    for sample in samples.iter_mut().take(order as usize) {
+        *sample = reader
+            .read_signed_var::<i64>(bps)
+            .map_err(|e| error::io(e, reader))?;
+    }
+
+    // декодирую residual он же остаток, он ложится на место сэмплов после прогревочных
+    decode_rice_residual(reader, order, samples)?;
+
+    // применяю предсказание для каждого сэмпла начиная с order до конца блока
+    // тест для работы с индексами вектора так как при n = 0 будет ошибка
+    // считаю в i128: в битом потоке остатки могут быть любыми и i64 переполнится
+    for n in order as usize..samples.len() {
+        let sample = |i: usize| i128::from(samples[n - i]);
+        let prediction = match order {
+            // 0
+            0 => 0,
+            // a(n-1)
+            1 => sample(1),
+            // 2 * a(n-1) - a(n-2)
+            2 => 2 * sample(1) - sample(2),
+            // 3 * a(n-1) - 3 * a(n-2) + a(n-3)
+            3 => 3 * sample(1) - 3 * sample(2) + sample(3),
+            // 4 * a(n-1) - 6 * a(n-2) + 4 * a(n-3) - a(n -4)
+            4 => 4 * sample(1) - 6 * sample(2) + 4 * sample(3) - sample(4),
+            _ => unreachable!(),
+        };
+
+        samples[n] = predicted_sample(reader, bps, prediction, samples[n])?;
+    }
+
+    Ok(())
+}
+
+// сэмпл из предсказания и остатка, ошибка если он не помещается в bps бит со знаком
+// в битом потоке остатки могут быть любыми, а на проверенных сэмплах
+// межканальная декорреляция уже не переполняется
+fn predicted_sample<R: Read + Seek>(
+    reader: &mut BitReader<R, BigEndian>,
+    bps: u32,
+    prediction: i128,
+    residual: i64,
+) -> Result<i64> {
+    let sample = prediction + i128::from(residual);
+    let limit = 1i128 << (bps - 1);
+    if !(-limit..limit).contains(&sample) {
+        return Err(FlacError::InvalidData {
+            reason: "Predicted sample exceeds subframe bit depth",
+            offset: error::position(reader),
+        });
+    }
+
+    // bps не больше 33, значение помещается в i64
+    #[allow(clippy::cast_possible_truncation)]
+    Ok(sample as i64)
+}
+
+// декодирование остатка (residual) закодированного кодом Райса
+// записывает block_size - order значений в samples после прогревочных сэмплов
+fn decode_rice_residual<R: Read + Seek>(
+    reader: &mut BitReader<R, BigEndian>,
+    order: u8,
+    samples: &mut [i64],
+) -> Result<()> {
+    // 2 бита - метод кодирования
+    // 0b00 - 4 битный параметр Райса, 0b01 - 5 битный (RICE2)
+    let parameter_bits = match reader.read::<2, u8>().map_err(|e| error::io(e, reader))? {
+        0b00 => 4,
+        0b01 => 5,
+        _ => {
+            return Err(FlacError::Reserved {
+                field: "residual coding method",
+                offset: error::position(reader),
+            });
+        }
+    };
+    // параметр из одних единиц означает escape партицию
+    let escape_code = (1u32 << parameter_bits) - 1;
+
+    // 4 бита - порядок разбиения, количество партиций = 2^order
+    let partition_order = reader.read::<4, u32>().map_err(|e| error::io(e, reader))?;
+    let block_size = samples.len();
+    let partition_size = block_size >> partition_order;
+
+    // размер блока должен делиться на количество партиций без остатка,
+    // а первая партиция должна вмещать прогревочные сэмплы
+    if partition_size << partition_order != block_size || partition_size < usize::from(order) {
+        return Err(FlacError::InvalidData {
+            reason: "Invalid residual partition order",
+            offset: error::position(reader),
+        });
+    }
+
+    // первая партиция короче на количество прогревочных сэмплов
+    let mut start = usize::from(order);
+
+    for partition in 0..1usize << partition_order {
+        let end = (partition + 1) * partition_size;
+        let residual = &mut samples[start..end];
+        start = end;
+
+        let rice_parameter = reader
+            .read_var::<u32>(parameter_bits)
+            .map_err(|e| error::io(e, reader))?;
+
+        if rice_parameter == escape_code {
+            // 5 бит - ширина сырых значений, 0 значит все остатки нулевые
+            let raw_bits = reader.read::<5, u32>().map_err(|e| error::io(e, reader))?;
+            for value in residual.iter_mut() {
+                *value = if raw_bits == 0 {
+                    0
+                } else {
+                    reader
+                        .read_signed_var::<i64>(raw_bits)
+                        .map_err(|e| error::io(e, reader))?
+                };
+            }
+        } else {
+            for value in residual.iter_mut() {
+                *value = read_rice_value(reader, rice_parameter)?;
+            }
+        }
+    }
+
+    Ok(())
+}
+
+// чтение одного значения закодированного кодом Райса с параметром k
+fn read_rice_value<R: Read + Seek>(
+    reader: &mut BitReader<R, BigEndian>,
+    rice_parameter: u32,
+) -> Result<i64> {
+    // старшая часть закодирована унарно, младшая - rice_parameter битами
+    let quotient = u64::from(reader.read_unary::<1>().map_err(|e| error::io(e, reader))?);
+    let remainder = if rice_parameter > 0 {
+        reader
+            .read_var::<u64>(rice_parameter)
+            .map_err(|e| error::io(e, reader))?
+    } else {
+        0
+    };
+    let folded = (quotient << rice_parameter) | remainder;
+
+    // zigzag: 0, -1, 1, -2, 2 ...
+    #[allow(clippy::cast_possible_wrap)]
+    Ok((folded >> 1) as i64 ^ -((folded & 1) as i64))
+}
+
+// предсказание линейным кодированием (LPC) с коэффициентами из потока
+fn lpc<R: Read + Seek>(
+    reader: &mut BitReader<R, BigEndian>,
+    order: u8,
+    bps: u32,
+    samples: &mut [i64],
+) -> Result<()> {
+    // прогревочные сэмплы
+    for sample in samples.iter_mut().take(order as usize) {
+        *sample = reader
+            .read_signed_var::<i64>(bps)
+            .map_err(|e| error::io(e, reader))?;
+    }
+
+    // 4 бита - точность коэффициентов минус 1, 0b1111 запрещено
+    let precision = reader.read::<4, u32>().map_err(|e| error::io(e, reader))?;
+    if precision == 0b1111 {
+        return Err(FlacError::Reserved {
+            field: "LPC coefficient precision",
+            offset: error::position(reader),
+        });
+    }
+    let precision = precision + 1;
+
+    // 5 бит - сдвиг со знаком, отрицательный запрещен
+    let shift = reader
+        .read_signed::<5, i32>()
+        .map_err(|e| error::io(e, reader))?;
+    if shift < 0 {
+        return Err(FlacError::InvalidData {
+            reason: "Negative LPC shift",
+            offset: error::position(reader),
+        });
+    }
+
+    // коэффициенты предсказания, порядок не больше 32
+    let mut coefficients = [0i64; 32];
+    let coefficients = &mut coefficients[..order as usize];
+    for coefficient in coefficients.iter_mut() {
+        *coefficient = reader
+            .read_signed_var::<i64>(precision)
+            .map_err(|e| error::io(e, reader))?;
+    }
+
+    decode_rice_residual(reader, order, samples)?;
+
+    // a(n) = (sum(c(j) * a(n-1-j)) >> shift) + residual(n)
+    for n in order as usize..samples.len() {
+        // коэффициенты не длиннее 15 бит, сумма 32 произведений помещается в i128
+        let prediction: i128 = coefficients
+            .iter()
+            .enumerate()
+            .map(|(j, &c)| i128::from(c) * i128::from(samples[n - 1 - j]))
+            .sum();
+
+        samples[n] = predicted_sample(reader, bps, prediction >> shift, samples[n])?;
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Cursor;
+
+    use bitstream_io::{BitWrite, BitWriter};
+
+    use super::*;
+
+    // битовый поток сабфрейма, пишется функцией write, дополняется нулями до байта
+    fn subframe_bits(write: impl FnOnce(&mut BitWriter<Vec<u8>, BigEndian>)) -> Vec<u8> {
+        let mut writer = BitWriter::endian(Vec::new(), BigEndian);
+        write(&mut writer);
+        writer.byte_align().unwrap();
+        writer.into_writer()
+    }
+
+    fn decode(bytes: Vec<u8>, bps: u32, samples: &mut [i64]) -> Result<SubframeHeader> {
+        let mut reader = BitReader::endian(Cursor::new(bytes), BigEndian);
+        decode_subframe_into(&mut reader, bps, samples)
+    }
+
+    // значение кодом Райса с параметром k: zigzag, унарная старшая часть и k младших бит
+    fn write_rice(writer: &mut BitWriter<Vec<u8>, BigEndian>, k: u32, value: i64) {
+        let folded = if value < 0 {
+            value.unsigned_abs() * 2 - 1
+        } else {
+            value.unsigned_abs() * 2
+        };
+        writer
+            .write_unary::<1>(u32::try_from(folded >> k).unwrap())
+            .unwrap();
+        writer.write_var(k, folded & ((1 << k) - 1)).unwrap();
+    }
+
+    #[test]
+    fn escape_partition() {
+        // FIXED порядка 0: сэмплы равны остаткам
+        // две партиции: первая escape с 5 битными сырыми значениями, вторая - Райс с k = 2
+        let bytes = subframe_bits(|writer| {
+            writer.write::<8, u8>(0b0001_0000).unwrap();
+            writer.write::<2, u8>(0b00).unwrap();
+            writer.write::<4, u8>(1).unwrap();
+
+            writer.write::<4, u8>(0b1111).unwrap();
+            writer.write::<5, u8>(5).unwrap();
+            for value in [-16, 15, 0, -1] {
+                writer.write_signed::<5, i8>(value).unwrap();
+            }
+
+            writer.write::<4, u8>(2).unwrap();
+            for value in [3, -2, 0, 5] {
+                write_rice(writer, 2, value);
+            }
+        });
+
+        let mut samples = [0; 8];
+        let header = decode(bytes, 16, &mut samples).unwrap();
+        assert_eq!(header.subframe_type, SubframeType::Fixed(0));
+        assert_eq!(samples, [-16, 15, 0, -1, 3, -2, 0, 5]);
+    }
+
+    #[test]
+    fn rice2_partition() {
+        // 5 битный параметр Райса больше 15 есть только в RICE2
+        let values = [1_000_000, -2_000_000, 7, 0];
+        let bytes = subframe_bits(|writer| {
+            writer.write::<8, u8>(0b0001_0000).unwrap();
+            writer.write::<2, u8>(0b01).unwrap();
+            writer.write::<4, u8>(0).unwrap();
+            writer.write::<5, u8>(20).unwrap();
+            for value in values {
+                write_rice(writer, 20, value);
+            }
+        });
+
+        let mut samples = [0; 4];
+        decode(bytes, 24, &mut samples).unwrap();
+        assert_eq!(samples, values);
+    }
+
+    #[test]
+    fn lpc_overflow_is_invalid_data() {
+        // LPC порядка 1 с максимальным коэффициентом: уже второй сэмпл не помещается в 33 бита
+        let bytes = subframe_bits(|writer| {
+            writer.write::<8, u8>(0b0100_0000).unwrap();
+            writer.write_signed::<33, i64>((1 << 32) - 1).unwrap();
+            writer.write::<4, u8>(14).unwrap();
+            writer.write_signed::<5, i8>(0).unwrap();
+            writer.write_signed::<15, i16>(0x3FFF).unwrap();
+            // escape партиция с нулевыми остатками
+            writer.write::<2, u8>(0b00).unwrap();
+            writer.write::<4, u8>(0).unwrap();
+            writer.write::<4, u8>(0b1111).unwrap();
+            writer.write::<5, u8>(0).unwrap();
+        });
+
+        let mut samples = [0; 8];
+        assert!(matches!(
+            decode(bytes, 33, &mut samples),
+            Err(FlacError::InvalidData {
+                reason: "Predicted sample exceeds subframe bit depth",
+                ..
+            })
+        ));
+    }
+
+    // FIXED порядка 1 в 16 битах: прогрев 32767 и один остаток
+    fn fixed_order_1(residual: i64) -> Result<[i64; 2]> {
+        let bytes = subframe_bits(|writer| {
+            writer.write::<8, u8>(0b0001_0010).unwrap();
+            writer.write_signed::<16, i16>(i16::MAX).unwrap();
+            writer.write::<2, u8>(0b00).unwrap();
+            writer.write::<4, u8>(0).unwrap();
+            writer.write::<4, u8>(0).unwrap();
+            write_rice(writer, 0, residual);
+        });
+
+        let mut samples = [0; 2];
+        decode(bytes, 16, &mut samples)?;
+        Ok(samples)
+    }
+
+    #[test]
+    fn fixed_prediction_is_bounded_by_bit_depth() {
+        // 32768 помещается в i64, но не в 16 бит
+        assert_eq!(fixed_order_1(0).unwrap(), [32767, 32767]);
+        assert!(matches!(
+            fixed_order_1(1),
+            Err(FlacError::InvalidData {
+                reason: "Predicted sample exceeds subframe bit depth",
+                ..
+            })
+        ));
+    }
+}
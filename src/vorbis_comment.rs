@@ -0,0 +1,165 @@
+use crate::error::{FlacError, Offset, Result};
+use crate::metedata_blocks::BlockReader;
+
+const UTF8_ERROR: &str = "Vorbis comment is not valid UTF-8";
+
+// теги из блока VORBIS_COMMENT
+// поля хранятся в порядке файла, имя может повторяться: несколько ARTIST и т.п.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct VorbisComment {
+    pub vendor: String,
+    // имя поля как записано в файле и значение
+    pub comments: Vec<(String, String)>,
+    // записи, которые не разбираются как NAME=value: пустые, без '=', не UTF-8
+    // хранятся ради записи блока обратно байт в байт
+    // индекс поля в comments, перед которым стояла запись, и ее байты
+    pub unparsed: Vec<(usize, Vec<u8>)>,
+}
+
+impl VorbisComment {
+    pub fn process_vorbis_comment_block(vorbis_comment_block: &[u8], offset: u64) -> Result<Self> {
+        let mut reader = BlockReader::new(vorbis_comment_block, 4, offset);
+
+        // в отличие от остального FLAC длины тут little-endian, как в Ogg Vorbis
+        let vendor_length = reader.read_u32_le()?;
+        let vendor = reader.read_string(vendor_length, UTF8_ERROR)?;
+
+        let comment_count = reader.read_u32_le()?;
+
+        // каждое поле занимает хотя бы 4 байта длины, не доверяю счетчику при выделении памяти
+        let mut comments = Vec::with_capacity((comment_count as usize).min(reader.remaining() / 4));
+        let mut unparsed = Vec::new();
+
+        for _ in 0..comment_count {
+            let comment_length = reader.read_u32_le()?;
+            let comment = reader.read_bytes(comment_length)?;
+
+            // поле имеет вид NAME=value, имя из печатных ASCII символов кроме '='
+            // из-за одного битого поля остальные теги не теряются
+            let field = str::from_utf8(comment)
+                .ok()
+                .and_then(|comment| comment.split_once('='))
+                .filter(|(name, _)| is_valid_name(name));
+            match field {
+                Some((name, value)) => comments.push((name.to_string(), value.to_string())),
+                None => unparsed.push((comments.len(), comment.to_vec())),
+            }
+        }
+
+        Ok(VorbisComment {
+            vendor,
+            comments,
+            unparsed,
+        })
+    }
+
+    // содержимое блока VORBIS_COMMENT
+    #[must_use]
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut bytes = Vec::new();
+        write_bytes(&mut bytes, self.vendor.as_bytes());
+        #[allow(clippy::cast_possible_truncation)]
+        let count = (self.comments.len() + self.unparsed.len()) as u32;
+        bytes.extend_from_slice(&count.to_le_bytes());
+
+        // сырые записи встают перед своим полем, после удаления полей - в конец
+        let mut unparsed = self.unparsed.iter().peekable();
+        for (index, (name, value)) in self.comments.iter().enumerate() {
+            while let Some((_, comment)) = unparsed.next_if(|(position, _)| *position <= index) {
+                write_bytes(&mut bytes, comment);
+            }
+            write_bytes(&mut bytes, format!("{name}={value}").as_bytes());
+        }
+        for (_, comment) in unparsed {
+            write_bytes(&mut bytes, comment);
+        }
+        bytes
+    }
+
+    // первое значение поля, имя без учета регистра
+    #[must_use]
+    pub fn get(&self, name: &str) -> Option<&str> {
+        self.comments
+            .iter()
+            .find(|(field, _)| field.eq_ignore_ascii_case(name))
+            .map(|(_, value)| value.as_str())
+    }
+
+    // все значения поля в порядке файла
+    pub fn get_all<'a>(&'a self, name: &'a str) -> impl Iterator<Item = &'a str> + 'a {
+        self.comments
+            .iter()
+            .filter(move |(field, _)| field.eq_ignore_ascii_case(name))
+            .map(|(_, value)| value.as_str())
+    }
+
+    // заменяет все значения поля одним, новое поле добавляется в конец
+    pub fn set(&mut self, name: &str, value: &str) -> Result<()> {
+        check_name(name)?;
+
+        // первое значение остается на своем месте, остальные удаляются
+        let mut found = false;
+        self.comments.retain_mut(|(field, old_value)| {
+            if !field.eq_ignore_ascii_case(name) {
+                return true;
+            }
+            if found {
+                return false;
+            }
+            found = true;
+            *old_value = value.to_string();
+            true
+        });
+
+        if !found {
+            self.add(name, value)?;
+        }
+        Ok(())
+    }
+
+    // еще одно значение поля, существующие не меняются
+    pub fn add(&mut self, name: &str, value: &str) -> Result<()> {
+        check_name(name)?;
+        self.comments.push((name.to_string(), value.to_string()));
+        Ok(())
+    }
+
+    // удаляет все значения поля
+    pub fn remove(&mut self, name: &str) {
+        self.comments
+            .retain(|(field, _)| !field.eq_ignore_ascii_case(name));
+    }
+
+    // все поля в порядке файла
+    pub fn iter(&self) -> impl Iterator<Item = (&str, &str)> {
+        self.comments
+            .iter()
+            .map(|(name, value)| (name.as_str(), value.as_str()))
+    }
+}
+
+// символы 0x20..=0x7D кроме '=', как требует спецификация Vorbis
+fn is_valid_name(name: &str) -> bool {
+    !name.is_empty()
+        && name
+            .bytes()
+            .all(|byte| (0x20..=0x7D).contains(&byte) && byte != b'=')
+}
+
+// имя поля, заданное вызывающим: в файле оно должно разбираться обратно
+fn check_name(name: &str) -> Result<()> {
+    if is_valid_name(name) {
+        Ok(())
+    } else {
+        Err(FlacError::InvalidData {
+            reason: "Invalid Vorbis comment field name",
+            offset: Offset::from_bytes(0),
+        })
+    }
+}
+
+fn write_bytes(bytes: &mut Vec<u8>, data: &[u8]) {
+    #[allow(clippy::cast_possible_truncation)]
+    bytes.extend_from_slice(&(data.len() as u32).to_le_bytes());
+    bytes.extend_from_slice(data);
+}